@@ -0,0 +1,64 @@
+//! Parsing of the arguments shared by the subcommands and the control socket's commands,
+//! so that both accept the same syntax (e.g. `promote M1 G13 --force`)
+
+use derive_more::{Display, Error};
+
+/// Parses the M/G coordinates of a key, each written as `M1`/`G13` (or `m1`/`g13`), or just the number
+pub fn parse_coordinates(m: &str, g: &str) -> Result<(u8, u8), ArgumentError> {
+    Ok((parse_key('M', m, 3)?, parse_key('G', g, 18)?))
+}
+
+fn parse_key(prefix: char, key: &str, max: u8) -> Result<u8, ArgumentError> {
+    key.strip_prefix([prefix, prefix.to_ascii_lowercase()]).unwrap_or(key)
+        .parse().ok()
+        .filter(|number| (1..=max).contains(number))
+        .ok_or_else(|| ArgumentError::InvalidKey(format!("{key} (expected {prefix}1 to {prefix}{max})")))
+}
+
+/// Whether `--force` is given (being the only option)
+pub fn parse_force(flags: &[&str]) -> Result<bool, ArgumentError> {
+    match flags {
+        [] => Ok(false),
+        ["--force"] => Ok(true),
+        [unknown, ..] => Err(ArgumentError::UnrecognizedOption(unknown.to_string())),
+    }
+}
+
+/// Parses the arguments of `promote all [--force]` or `promote <M> <G> [--force]`:
+/// the coordinates (if not `all`) and whether to overwrite
+pub fn parse_promotion(args: &[&str]) -> Result<(Option<(u8, u8)>, bool), ArgumentError> {
+    match args {
+        ["all", flags @ ..] => Ok((None, parse_force(flags)?)),
+        [m, g, flags @ ..] => Ok((Some(parse_coordinates(m, g)?), parse_force(flags)?)),
+        _ => Err(ArgumentError::MissingKey),
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ArgumentError {
+    #[display("Invalid key: {_0}")]
+    InvalidKey(#[error(not(source))] String),
+    #[display("Unrecognized option: {_0}")]
+    UnrecognizedOption(#[error(not(source))] String),
+    #[display("Expected the M and G keys (or all)")]
+    MissingKey,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_promotions() {
+        assert_eq!(parse_promotion(&["all"]).ok(), Some((None, false)));
+        assert_eq!(parse_promotion(&["M2", "g13", "--force"]).ok(), Some((Some((2, 13)), true)));
+        assert_eq!(parse_promotion(&["3", "18"]).ok(), Some((Some((3, 18)), false)));
+
+        assert!(matches!(parse_promotion(&["M4", "G1"]), Err(ArgumentError::InvalidKey(_))));
+        assert!(matches!(parse_promotion(&["M1", "G19"]), Err(ArgumentError::InvalidKey(_))));
+        assert!(matches!(parse_promotion(&["M1", "G0"]), Err(ArgumentError::InvalidKey(_))));
+        assert!(matches!(parse_promotion(&["all", "force"]), Err(ArgumentError::UnrecognizedOption(_))));
+        assert!(matches!(parse_promotion(&["M1"]), Err(ArgumentError::MissingKey)));
+    }
+}
@@ -4,7 +4,7 @@ use enigo::Direction;
 use log::warn;
 use smallvec::SmallVec;
 use g11_macro_keys::{Action, Event, Key};
-use crate::{config::KeyBinding, steps::Step};
+use crate::{config::{self, KeyBinding, Macros}, steps::Step};
 
 pub struct BindingBanks {
     /// Zero-indexed (respective M key minus one)
@@ -13,17 +13,18 @@ pub struct BindingBanks {
     release_banks: Vec<BindingBank>, //Release is less common; trade a heap lookup for smaller stack
     /// Zero-indexed (respective M key minus one)
     active_bank: u8,
+    /// Called by the scripts of the bindings, which are expanded as they are added
+    macros: Macros,
 }
-impl From<Vec<KeyBinding>> for BindingBanks {
-    fn from(bindings: Vec<KeyBinding>) -> Self {
-        let mut banks = Self { press_banks: Default::default(), release_banks: Default::default(), active_bank: 0 };
+impl BindingBanks {
+    pub fn new(bindings: Vec<KeyBinding>, macros: Macros) -> Self {
+        let mut banks = Self { press_banks: Default::default(), release_banks: Default::default(), active_bank: 0, macros };
         for binding in bindings {
             banks.replace(binding);
         }
         banks
     }
-}
-impl BindingBanks {
+
     /// Ignores invalid M Keys
     pub fn activate_bank(&mut self, m_key: u8) {
         if let Some(bank_index) = Self::bank_index(m_key) {
@@ -31,12 +32,12 @@ impl BindingBanks {
         }
     }
 
-    pub fn script_for(&self, g_key_event: Event) -> Option<impl IntoIterator<Item = &Step>> {
+    pub fn binding_for(&self, g_key_event: Event) -> Option<&Bound> {
         match g_key_event {
             Event { key: Key::G(g_key), action: Action::Pressed } =>
-                self.press_banks[self.active_bank as usize].script_for(g_key),
+                self.press_banks[self.active_bank as usize].binding_for(g_key),
             Event { key: Key::G(g_key), action: Action::Released } =>
-                self.release_banks.get(self.active_bank as usize)?.script_for(g_key),
+                self.release_banks.get(self.active_bank as usize)?.binding_for(g_key),
             _ => None,
         }
 
@@ -51,13 +52,35 @@ impl BindingBanks {
             _ => Some(m_key as usize - 1),
         }
     }
-    pub fn replace(&mut self, binding: KeyBinding) {
+    /// Sets the binding at its coordinates, with any macro calls in its script expanded
+    pub fn replace(&mut self, mut binding: KeyBinding) {
+        binding.script = config::expand_macro_calls(binding.script, &self.macros);
         match (Self::bank_index(binding.m), binding.on) {
-            (None, _) => warn!("Ignoring invalid KeyBinding (there is no M{} key)", binding.m),
+            (None, _) => warn!("Ignoring invalid KeyBinding {} (there is no M{} key)", binding.describe(), binding.m),
             (Some(bank_index), Direction::Press) => self.press_banks[bank_index].replace(binding),
             (Some(bank_index), _) => self.ensure_release_bank(bank_index).replace(binding),
         }
     }
+    /// Gives a newly recorded binding the name, description and label of the binding in effect at its coordinates (if any),
+    /// which it is about to take the place of
+    pub fn inherit_metadata(&self, recorded: KeyBinding) -> KeyBinding {
+        let bank = match (Self::bank_index(recorded.m), recorded.on) {
+            (Some(bank_index), Direction::Press) => self.press_banks.get(bank_index),
+            (Some(bank_index), _) => self.release_banks.get(bank_index),
+            (None, _) => None,
+        };
+        match bank.and_then(|bank| bank.binding_for(recorded.g)) {
+            Some(Bound { metadata: Metadata { name, description, label }, .. }) => {
+                let replaced = KeyBinding { name: name.clone(), description: description.clone(), label: label.clone(), ..KeyBinding::new(recorded.m, recorded.g, recorded.on, vec![]) };
+                recorded.inheriting_from(replaced)
+            }
+            None => recorded,
+        }
+    }
+    /// Leaves the key at the given coordinates unbound
+    pub fn remove(&mut self, m: u8, g: u8, on: Direction) {
+        self.replace(KeyBinding::new(m, g, on, vec![]));
+    }
     fn ensure_release_bank(&mut self, bank_index: usize) -> &mut BindingBank {
         if bank_index >= self.release_banks.len() {
             self.release_banks.resize_with(bank_index + 1, Default::default);
@@ -69,10 +92,26 @@ impl BindingBanks {
 /// Optimised for the typical binding: a one-modifier click
 pub type Script = SmallVec<[Step; 3]>;
 
+/// A script, along with a description of its binding (see [`KeyBinding::describe`]) for logging
+#[derive(Default)]
+pub struct Bound {
+    pub description: String,
+    pub script: Script,
+    pub metadata: Metadata,
+}
+
+/// The name, description and label of a binding (see [`KeyBinding`])
+#[derive(Default)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub label: Option<String>,
+}
+
 /// All G-key mappings under a specific M-key.
 /// Zero-indexed (respective G key minus one)
 #[derive(Default)]
-struct BindingBank(Vec<Script>);
+struct BindingBank(Vec<Bound>);
 impl BindingBank {
     fn script_index(m_key: u8) -> Option<usize> {
         match m_key {
@@ -81,7 +120,7 @@ impl BindingBank {
         }
     }
 
-    fn script_for(&self, g_key: u8) -> Option<&Script> {
+    fn binding_for(&self, g_key: u8) -> Option<&Bound> {
         Self::script_index(g_key)
             .and_then(|index| self.0.get(index))
     }
@@ -91,9 +130,32 @@ impl BindingBank {
             if script_index >= self.0.len() {
                 self.0.resize_with(script_index + 1, Default::default);
             }
-            self.0[script_index] = binding.script.into_iter().collect();
+            let description = binding.describe();
+            let KeyBinding { name, description: about, label, script, .. } = binding;
+            self.0[script_index] = Bound { description, script: script.into_iter().collect(), metadata: Metadata { name, description: about, label } };
         } else {
-            warn!("Ignoring invalid KeyBinding (there is no G{} key)", binding.g);
+            warn!("Ignoring invalid KeyBinding {} (there is no G{} key)", binding.describe(), binding.g);
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Direction::*;
+    use super::*;
+
+    #[test]
+    fn recordings_inherit_the_metadata_in_effect() {
+        let named = KeyBinding { name: Some("greet".into()), label: Some("Hi".into()), ..KeyBinding::new(2, 5, Release, vec![Step::Text("hi".into())]) };
+        let banks = BindingBanks::new(vec![named], Macros::new());
+
+        let recorded = KeyBinding { label: Some("Hello".into()), ..KeyBinding::new(2, 5, Release, vec![Step::Text("hello".into())]) };
+        assert_eq!(
+            banks.inherit_metadata(recorded.clone()),
+            KeyBinding { name: Some("greet".into()), label: Some("Hello".into()), ..recorded.clone() },
+        );
+        assert_eq!(banks.inherit_metadata(KeyBinding { on: Press, ..recorded.clone() }), KeyBinding { on: Press, ..recorded.clone() });
+        assert_eq!(banks.inherit_metadata(KeyBinding { m: 1, ..recorded.clone() }), KeyBinding { m: 1, ..recorded });
+    }
+}
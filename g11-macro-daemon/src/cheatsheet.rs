@@ -0,0 +1,201 @@
+//! Printable cheat-sheets of the bindings in effect, drawing each bank's G keys as they are laid out on the keyboard
+//! (see the README), each with its label or else a summary of its script
+
+use enigo::Direction;
+use crate::{
+    config::{self, BindingsFile, KeyBinding, LoadError},
+    render::{escape, summarize},
+};
+
+/// How many characters of a legend fit on a key (beyond which it is truncated)
+const LEGEND_LENGTH: usize = 22;
+
+/// Cheat-sheet formats
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Svg,
+    Html,
+}
+
+/// Renders the cheat-sheet of the bindings in the config files
+pub fn export(format: Format) -> Result<String, LoadError> {
+    let sheet = Sheet::new(
+        config::load_bindings_file(BindingsFile::KeyBindings)?,
+        config::load_bindings_file(BindingsFile::KeyRecordings)?,
+    );
+    Ok(match format {
+        Format::Svg => sheet.svg(),
+        Format::Html => sheet.html(),
+    })
+}
+
+pub struct Sheet {
+    key_bindings: Vec<KeyBinding>,
+    recordings: Vec<KeyBinding>,
+}
+impl Sheet {
+    pub fn new(key_bindings: Vec<KeyBinding>, recordings: Vec<KeyBinding>) -> Self {
+        Self { key_bindings, recordings }
+    }
+
+    /// The legend of the binding in effect at the given coordinates (a recording taking precedence over a static binding):
+    /// its label, or else its name, or else a summary of its script. Also returns the binding's description, if any.
+    fn legend(&self, m: u8, g: u8, on: Direction) -> Option<Legend> {
+        let binding = self.recordings.iter().rfind(|binding| binding.is_at(m, g, on))
+            .or_else(|| self.key_bindings.iter().rfind(|binding| binding.is_at(m, g, on)))?;
+        let text = binding.label.clone().or_else(|| binding.name.clone()).unwrap_or_else(|| summarize(&binding.script));
+        Some(Legend { tooltip: binding.description.clone().unwrap_or_else(|| text.clone()), text })
+    }
+
+    /// The legends of the given key, on press and on release (the latter marked with an arrow)
+    fn legends(&self, m: u8, g: u8) -> Vec<Legend> {
+        [
+            self.legend(m, g, Direction::Press),
+            self.legend(m, g, Direction::Release).map(|legend| Legend { text: format!("↑ {}", legend.text), ..legend }),
+        ].into_iter().flatten().collect()
+    }
+
+    /// One panel per bank, side by side
+    pub fn svg(&self) -> String {
+        const KEY_WIDTH: u32 = 140;
+        const KEY_HEIGHT: u32 = 52;
+        const SPACING: u32 = 6;
+        const GROUP_SPACING: u32 = 18;
+        const PANEL_WIDTH: u32 = 3 * KEY_WIDTH + 2 * SPACING;
+        const MARGIN: u32 = 20;
+
+        let mut keys = String::new();
+        for m in 1..=3 {
+            let left = MARGIN + (u32::from(m) - 1) * (PANEL_WIDTH + 2 * MARGIN);
+            keys.push_str(&format!("<text x=\"{left}\" y=\"{}\" class=\"bank\">M{m}</text>\n", MARGIN + 14));
+            for g in 1..=18_u8 {
+                let (row, column) = (u32::from(g - 1) / 3, u32::from(g - 1) % 3);
+                let x = left + column * (KEY_WIDTH + SPACING);
+                let y = MARGIN + 28 + row * (KEY_HEIGHT + SPACING) + row / 2 * GROUP_SPACING;
+                keys.push_str(&format!(
+                    "<g><rect x=\"{x}\" y=\"{y}\" width=\"{KEY_WIDTH}\" height=\"{KEY_HEIGHT}\" rx=\"6\"/>\
+                     <text x=\"{}\" y=\"{}\" class=\"g\">G{g}</text>",
+                    x + 6, y + 13,
+                ));
+                for (line, Legend { text, tooltip }) in self.legends(m, g).iter().enumerate() {
+                    keys.push_str(&format!(
+                        "<text x=\"{}\" y=\"{}\" class=\"legend\"><title>{}</title>{}</text>",
+                        x + 6, y + 29 + line as u32 * 15, escape(tooltip), escape(&truncate(text)),
+                    ));
+                }
+                keys.push_str("</g>\n");
+            }
+        }
+        let width = 3 * (PANEL_WIDTH + 2 * MARGIN);
+        let height = 2 * MARGIN + 28 + 6 * KEY_HEIGHT + 5 * SPACING + 2 * GROUP_SPACING;
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" font-family=\"sans-serif\">\n\
+             <style>rect{{fill:none;stroke:#333}}.bank{{font-size:16px;font-weight:bold}}.g{{font-size:10px;fill:#777}}.legend{{font-size:12px}}</style>\n\
+             {keys}</svg>\n"
+        )
+    }
+
+    /// One section per bank (each group of two rows of G keys being a table, spaced apart as on the keyboard)
+    pub fn html(&self) -> String {
+        let mut banks = String::new();
+        for m in 1..=3 {
+            let mut groups = String::new();
+            for rows in [0..2_u8, 2..4, 4..6] {
+                groups.push_str("<table>");
+                for row in rows {
+                    groups.push_str("<tr>");
+                    for g in (1..=3).map(|column| row * 3 + column) {
+                        let legends: String = self.legends(m, g).iter()
+                            .map(|Legend { text, tooltip }| format!("<div class=\"legend\" title=\"{}\">{}</div>", escape(tooltip), escape(&truncate(text))))
+                            .collect();
+                        groups.push_str(&format!("<td><div class=\"g\">G{g}</div>{legends}</td>"));
+                    }
+                    groups.push_str("</tr>");
+                }
+                groups.push_str("</table>\n");
+            }
+            banks.push_str(&format!("<section><h2>M{m}</h2>\n{groups}</section>\n"));
+        }
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>G11 macros</title><style>\
+             body{{font-family:sans-serif}}table{{border-spacing:6px;margin-bottom:12px}}\
+             td{{border:1px solid #333;border-radius:6px;width:10em;height:3.5em;padding:4px 6px;vertical-align:top}}\
+             .g{{font-size:.7em;color:#777}}.legend{{font-size:.9em}}\
+             @media print{{section{{break-inside:avoid}}}}\
+             </style></head><body>\n{banks}</body></html>\n"
+        )
+    }
+}
+
+/// What is shown for a binding
+struct Legend {
+    text: String,
+    /// Shown upon hovering over the legend (so, not on paper)
+    tooltip: String,
+}
+
+fn truncate(legend: &str) -> String {
+    //Only once there is a character beyond those that fit is the last of them given up for the ellipsis
+    let mut ends = legend.char_indices().skip(LEGEND_LENGTH - 1).map(|(index, _)| index);
+    match (ends.next(), ends.next()) {
+        (Some(end), Some(_)) => format!("{}…", &legend[..end]),
+        _ => legend.into(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::{Direction::*, Key};
+    use crate::steps::Step;
+    use super::*;
+
+    fn binding(m: u8, g: u8, on: Direction, script: Vec<Step>, label: Option<&str>) -> KeyBinding {
+        KeyBinding { label: label.map(String::from), ..KeyBinding::new(m, g, on, script) }
+    }
+
+    #[test]
+    fn truncates_long_legends() {
+        assert_eq!(truncate("A rather long legend for a key"), "A rather long legend …");
+        assert_eq!(truncate("Copy"), "Copy");
+        assert_eq!(truncate("Exactly twenty-two ch."), "Exactly twenty-two ch.");
+        assert_eq!(truncate("Exactly twenty-three c."), "Exactly twenty-three …");
+    }
+
+    #[test]
+    fn labels_take_the_place_of_summaries() {
+        let sheet = Sheet::new(
+            vec![
+                binding(1, 1, Press, vec![Step::Text("static".into())], Some("<Copy>")),
+                binding(1, 2, Press, vec![Step::Text("static".into())], None),
+                binding(2, 18, Release, vec![Step::Key(Key::Tab, Click)], None),
+            ],
+            vec![binding(1, 2, Press, vec![Step::Text("recorded".into())], None)],
+        );
+
+        let svg = sheet.svg();
+        for text in [">M3<", ">G18<", ">&lt;Copy&gt;<", ">&quot;recorded&quot;<", ">↑ Tab<"] {
+            assert!(svg.contains(text), "{text} is in the SVG");
+        }
+        assert!(!svg.contains("static"));
+
+        let html = sheet.html();
+        for text in ["<h2>M3</h2>", ">G18<", ">&lt;Copy&gt;<", ">&quot;recorded&quot;<", ">↑ Tab<"] {
+            assert!(html.contains(text), "{text} is in the HTML");
+        }
+    }
+
+    #[test]
+    fn names_take_the_place_of_summaries_but_not_labels() {
+        let named = |label: Option<&str>| KeyBinding {
+            name: Some("close-tab".into()),
+            description: Some("Closes the current tab".into()),
+            ..binding(1, 1, Press, vec![Step::Key(Key::Unicode('w'), Click)], label)
+        };
+
+        let html = Sheet::new(vec![named(None)], vec![]).html();
+        assert!(html.contains("title=\"Closes the current tab\">close-tab<"));
+        let html = Sheet::new(vec![named(Some("Close"))], vec![]).html();
+        assert!(html.contains("title=\"Closes the current tab\">Close<"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Subcommands for managing the configuration, as an alternative to running the daemon itself
+
+use std::process;
+use enigo::Direction;
+use crate::{arguments, cheatsheet, config, control, edit, render, schema, tui};
+
+const USAGE: &str = "\
+Usage:
+  g11-macro-daemon                          Runs the daemon
+  g11-macro-daemon schema                   Prints a JSON Schema for the key bindings file
+  g11-macro-daemon tui                      Opens an interactive editor for the key bindings file
+  g11-macro-daemon list                     Lists the bindings in effect (with their names and descriptions)
+  g11-macro-daemon export <svg|html>        Prints a cheat-sheet of each bank's G keys, for printing
+  g11-macro-daemon reload                   Has the running daemon reload its config files
+  g11-macro-daemon restore [N]              Restores backup N (default 1, the most recent) of the recorded macros file
+  g11-macro-daemon edit <M> <G> [press|release]
+                                            Opens the script bound to the given M/G key in $EDITOR,
+                                            then has the running daemon reload it
+  g11-macro-daemon history                  Lists the recorded macros that may be undone
+  g11-macro-daemon undo [<M> <G>]           Undoes the most recent recording (at the given M/G key, if any),
+                                            restoring the recorded macro that it replaced
+  g11-macro-daemon promote <M> <G> [--force]
+  g11-macro-daemon promote all [--force]    Moves recorded macros (at the given M/G key, or all) into the key bindings file
+                                            (--force allows replacing existing static bindings)";
+
+/// Runs the given subcommand (the arguments following the binary name), exiting the process on failure
+pub fn run(args: &[&str]) {
+    match args {
+        ["schema"] => schema::print().expect("Unable to write schema"),
+        ["reload"] => reload_daemon(),
+        ["tui"] => if let Err(err) = tui::run() {
+            eprintln!("{err}");
+            process::exit(1);
+        },
+        ["list"] => list_bindings(),
+        ["export", format] => export_cheatsheet(match *format {
+            "svg" => cheatsheet::Format::Svg,
+            "html" => cheatsheet::Format::Html,
+            _ => exit_with_usage(&format!("Unrecognized cheat-sheet format: {format}")),
+        }),
+        ["restore"] => restore_recorded_macros(1),
+        ["restore", version] => match version.parse() {
+            Ok(version) => restore_recorded_macros(version),
+            Err(_) => exit_with_usage(&format!("Invalid backup version: {version}")),
+        },
+        ["edit", m, g] => edit_binding(parse_coordinates(m, g), None),
+        ["edit", m, g, on] => edit_binding(parse_coordinates(m, g), Some(parse_direction(on))),
+        ["history"] => list_recording_history(),
+        ["undo"] => undo_recorded_macro(None),
+        ["undo", m, g] => undo_recorded_macro(Some(parse_coordinates(m, g))),
+        ["promote", args @ ..] => match arguments::parse_promotion(args) {
+            Ok((coordinates, overwrite)) => promote_recorded_macros(coordinates, overwrite),
+            Err(err) => exit_with_usage(&err.to_string()),
+        },
+        [unknown, ..] => exit_with_usage(&format!("Unrecognized subcommand: {unknown}")),
+        [] => exit_with_usage("Missing subcommand"),
+    }
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{message}\n\n{USAGE}");
+    process::exit(2);
+}
+
+/// See [`arguments::parse_coordinates`]
+fn parse_coordinates(m: &str, g: &str) -> (u8, u8) {
+    arguments::parse_coordinates(m, g).unwrap_or_else(|err| exit_with_usage(&err.to_string()))
+}
+
+fn parse_direction(on: &str) -> Direction {
+    match on.to_ascii_lowercase().as_str() {
+        "press" => Direction::Press,
+        "release" => Direction::Release,
+        _ => exit_with_usage(&format!("Invalid trigger: {on} (expected press or release)")),
+    }
+}
+
+fn list_bindings() {
+    let load = |file| config::load_bindings_file(file).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+    let (key_bindings, recordings) = (load(config::BindingsFile::KeyBindings), load(config::BindingsFile::KeyRecordings));
+    //Where there are duplicates, the last one wins (as it does when loading), with recordings taking precedence
+    let mut bindings: Vec<(config::KeyBinding, bool)> = vec![];
+    for (binding, recorded) in key_bindings.into_iter().map(|binding| (binding, false)).chain(recordings.into_iter().map(|binding| (binding, true))) {
+        bindings.retain(|(earlier, _)| !earlier.is_at(binding.m, binding.g, binding.on));
+        bindings.push((binding, recorded));
+    }
+    bindings.sort_by_key(|(binding, _)| (binding.m, binding.g, binding.on == Direction::Release));
+
+    if bindings.is_empty() {
+        println!("There are no bindings.");
+    }
+    for (binding, recorded) in bindings {
+        println!(
+            "{}: {}{}",
+            binding.describe(), render::summarize(&binding.script), if recorded { " [recorded]" } else { "" },
+        );
+        if let Some(description) = &binding.description {
+            println!("    {description}");
+        }
+    }
+}
+
+fn export_cheatsheet(format: cheatsheet::Format) {
+    match cheatsheet::export(format) {
+        Ok(sheet) => print!("{sheet}"),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn restore_recorded_macros(version: usize) {
+    match config::restore_recorded_macros(version) {
+        Ok(path) => {
+            println!("Restored {} from backup {version}", path.display());
+            reload_daemon();
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            match config::list_recorded_macro_backups() {
+                Ok(backups) if backups.is_empty() => eprintln!("There are no backups available."),
+                Ok(backups) => {
+                    eprintln!("Available backups:");
+                    for (version, path) in backups {
+                        eprintln!("  {version}: {}", path.display());
+                    }
+                }
+                Err(err) => eprintln!("{err}"),
+            }
+            process::exit(1);
+        }
+    }
+}
+
+fn edit_binding((m, g): (u8, u8), on: Option<Direction>) {
+    let (binding, file) = match config::find_binding(m, g, on) {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            eprintln!("There is no binding for M{m} G{g}{}.", on.map_or(String::new(), |on| format!(" (on {on:?})")));
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+    let script = match edit::edit_script(&binding, |edited| config::validate_saved_calls(std::slice::from_ref(edited))) {
+        Ok(Some(script)) => script,
+        Ok(None) => {
+            println!("Nothing was changed.");
+            return;
+        }
+        Err(err) => {
+            eprintln!("Unable to edit the script: {err}");
+            process::exit(1);
+        }
+    };
+    match config::save_binding(config::KeyBinding { script, ..binding }, file) {
+        Ok(path) => println!("Saved M{m} G{g} to {}", path.display()),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+    reload_daemon();
+}
+
+/// Has the running daemon (if any) apply a change just made to the config files
+fn reload_daemon() {
+    match control::request_reload() {
+        Ok(()) => println!("The daemon has reloaded its config."),
+        Err(err @ control::ControlError::NotRunning(_)) => println!("{err}; the change applies once it is started."),
+        Err(err) => {
+            eprintln!("{err}\nRestart the service to apply the change.");
+            process::exit(1);
+        }
+    }
+}
+
+fn list_recording_history() {
+    match config::recording_history() {
+        Ok(history) if history.is_empty() => println!("There are no recordings to undo."),
+        Ok(history) => {
+            println!("Recordings that may be undone (most recent first):");
+            for entry in history.iter().rev() {
+                let replaced = entry.replaced.as_ref().map_or("nothing recorded".into(), |binding| format!("a {}-step script", binding.script.len()));
+                println!("  M{} G{} (on {:?}), which replaced {replaced}", entry.m, entry.g, entry.on);
+            }
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn undo_recorded_macro(coordinates: Option<(u8, u8)>) {
+    match config::undo_recorded_macro(coordinates) {
+        Ok(Some(undone)) => {
+            println!("Undid the recording at M{} G{} (on {:?})", undone.m, undone.g, undone.on);
+            reload_daemon();
+        }
+        Ok(None) => {
+            eprintln!("There are no recordings to undo.");
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn promote_recorded_macros(coordinates: Option<(u8, u8)>, overwrite: bool) {
+    match config::promote_recorded_macros(coordinates, overwrite) {
+        Ok(promoted) => {
+            for binding in promoted {
+                println!("Promoted M{} G{} (on {:?}) to {}", binding.m, binding.g, binding.on, config::XDG_CONFIG_KEY_BINDINGS);
+            }
+            reload_daemon();
+        }
+        Err(err @ config::PromoteError::WouldOverwrite(_)) => {
+            eprintln!("{err}\nRe-run with --force to replace them.");
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
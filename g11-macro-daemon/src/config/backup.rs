@@ -0,0 +1,168 @@
+//! Crash-safe replacement of files that the daemon rewrites, keeping numbered backups of previous versions
+//! (`<file>.1` being the most recent, up to `<file>.N`)
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use rustix::fs::{flock, FlockOperation};
+use tempfile::NamedTempFile;
+
+/// How many previous versions of a rewritten file are kept
+pub const BACKUP_COUNT: usize = 5;
+
+/// Replaces the file's contents without ever leaving it partially written:
+/// the new contents are written (and synced) to a uniquely named temporary file in the same directory,
+/// which is then renamed over the original (the temporary file being removed should anything fail before then).
+///
+/// The previous contents are kept as version `1`, with older versions shifted up until `backup_count` is reached.
+/// Concurrent writers of the same file should hold its [lock] throughout, lest one's update be lost to the other's.
+pub fn replace_with_backups(path: &Path, contents: &[u8], backup_count: usize) -> io::Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp_file = NamedTempFile::new_in(dir)?;
+    temp_file.write_all(contents)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        temp_file.as_file().set_permissions(metadata.permissions())?;
+    }
+    temp_file.as_file().sync_all()?;
+
+    if backup_count > 0 && path.try_exists()? {
+        remove_if_exists(&backup_path(path, backup_count))?;
+        for version in (1..backup_count).rev() {
+            let older = backup_path(path, version);
+            if older.try_exists()? {
+                fs::rename(&older, backup_path(path, version + 1))?;
+            }
+        }
+        //Linking (rather than renaming) ensures that the original path never goes missing
+        let latest_backup = backup_path(path, 1);
+        fs::hard_link(path, &latest_backup)
+            .or_else(|_| fs::copy(path, &latest_backup).map(|_| ()))?;
+    }
+
+    temp_file.persist(path).map_err(|err| err.error)?;
+    File::open(dir)?.sync_all()
+}
+
+/// Takes an exclusive lock on the given (lock) file, creating it if need be, which is held until the returned file is closed.
+/// Being an advisory [`flock`], it serializes the holders whether they are threads of one process or separate processes.
+pub fn lock(path: &Path) -> io::Result<File> {
+    let file = File::options().create(true).truncate(false).write(true).open(path)?;
+    flock(&file, FlockOperation::LockExclusive)?;
+    Ok(file)
+}
+
+/// Replaces the file with the given backup version.
+/// The replaced contents become the new version `1`, so a restore may itself be undone by restoring again.
+pub fn restore(path: &Path, version: usize, backup_count: usize) -> io::Result<()> {
+    let contents = fs::read(backup_path(path, version))?;
+    replace_with_backups(path, &contents, backup_count)
+}
+
+/// Lists the existing backups of the given file, most recent first
+pub fn list(path: &Path, backup_count: usize) -> io::Result<Vec<(usize, PathBuf)>> {
+    let mut backups = vec![];
+    for version in 1..=backup_count {
+        let backup = backup_path(path, version);
+        if backup.try_exists()? {
+            backups.push((version, backup));
+        }
+    }
+    Ok(backups)
+}
+
+pub fn backup_path(path: &Path, version: usize) -> PathBuf {
+    suffixed(path, &version.to_string())
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path);
+    name.push(".");
+    name.push(suffix);
+    name.into()
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> String { fs::read_to_string(path).expect("readable") }
+
+    #[test]
+    fn keeps_limited_number_of_backups() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("key_recordings.ron");
+
+        for version in 0..5 {
+            replace_with_backups(&path, format!("v{version}").as_bytes(), 3).expect("can replace");
+        }
+
+        assert_eq!(read(&path), "v4");
+        assert_eq!(read(&backup_path(&path, 1)), "v3");
+        assert_eq!(read(&backup_path(&path, 2)), "v2");
+        assert_eq!(read(&backup_path(&path, 3)), "v1");
+        assert!(!backup_path(&path, 4).exists());
+        assert_eq!(list(&path, 3).expect("can list").len(), 3);
+        assert_eq!(fs::read_dir(dir.path()).expect("can list dir").count(), 4, "no temporary files are left behind");
+    }
+
+    #[test]
+    fn removes_the_temporary_file_when_backups_fail() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("key_recordings.ron");
+        replace_with_backups(&path, b"old", 3).expect("can replace");
+        //A directory in place of the oldest backup cannot be removed as a file
+        fs::create_dir(backup_path(&path, 3)).expect("can create dir");
+
+        assert!(replace_with_backups(&path, b"new", 3).is_err());
+        assert_eq!(read(&path), "old");
+        assert_eq!(fs::read_dir(dir.path()).expect("can list dir").count(), 2, "no temporary files are left behind");
+    }
+
+    #[test]
+    fn locks_exclusively() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join(".lock");
+        let held = lock(&path).expect("can lock");
+
+        let other = File::options().write(true).open(&path).expect("can open");
+        assert!(flock(&other, FlockOperation::NonBlockingLockExclusive).is_err(), "the lock is held");
+        drop(held);
+        assert!(flock(&other, FlockOperation::NonBlockingLockExclusive).is_ok(), "the lock was released");
+    }
+
+    #[test]
+    fn restore_is_reversible() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("key_recordings.ron");
+        replace_with_backups(&path, b"old", 3).expect("can replace");
+        replace_with_backups(&path, b"new", 3).expect("can replace");
+
+        restore(&path, 1, 3).expect("can restore");
+        assert_eq!(read(&path), "old");
+        assert_eq!(read(&backup_path(&path, 1)), "new");
+
+        restore(&path, 1, 3).expect("can restore");
+        assert_eq!(read(&path), "new");
+    }
+
+    #[test]
+    fn restoring_missing_version_fails_without_changes() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("key_recordings.ron");
+        replace_with_backups(&path, b"only", 3).expect("can replace");
+
+        assert!(restore(&path, 2, 3).is_err());
+        assert_eq!(read(&path), "only");
+    }
+}
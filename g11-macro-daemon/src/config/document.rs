@@ -0,0 +1,472 @@
+//! Format-preserving editing of a key bindings file.
+//!
+//! Rather than deserializing the whole file and writing it back out (which would discard comments and formatting),
+//! the text is scanned just enough to locate each top-level `KeyBinding(..)` entry,
+//! so that an individual entry may be replaced or inserted while every other byte is left untouched.
+
+use std::ops::Range;
+use enigo::Direction;
+use ron::{
+    error::{Position, SpannedError},
+    extensions::Extensions,
+    ser::PrettyConfig,
+};
+use super::KeyBinding;
+
+/// Indentation used for new entries when there are no existing entries to imitate
+const DEFAULT_INDENT: &str = "    ";
+
+/// The text of a key bindings file, along with the location of each of its entries
+#[derive(Debug)]
+pub struct BindingsDocument {
+    text: String,
+    entries: Vec<Entry>,
+    /// Byte offset of the bracket that closes the outer list (if present)
+    list_end: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Byte range of the entry itself (excluding any trailing comma)
+    span: Range<usize>,
+    /// Byte offset of the comma following the entry (if present)
+    comma: Option<usize>,
+    binding: KeyBinding,
+}
+
+impl BindingsDocument {
+    /// Locates and parses each entry within the given text.
+    /// Like the loader, this is tolerant of one or both of the outer list brackets being absent.
+    pub fn parse(text: String) -> Result<Self, SpannedError> {
+        let Layout { entries, list_end } = scan(&text)?;
+        let ron = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+        let entries = entries.into_iter()
+            .map(|(span, comma)|
+                ron.from_str(&text[span.clone()])
+                    .map(|binding| Entry { span: span.clone(), comma, binding })
+                    .map_err(|err| offset_error(err, position_of(&text, span.start)))
+            )
+            .collect::<Result<_, _>>()?;
+        Ok(Self { text, entries, list_end })
+    }
+
+    pub fn as_str(&self) -> &str { &self.text }
+
+    /// Iterates over the bindings in the order that they appear in the text
+    pub fn bindings(&self) -> impl Iterator<Item = &KeyBinding> {
+        self.entries.iter().map(|entry| &entry.binding)
+    }
+
+    /// If an existing binding exists with the same coordinates, then its text will be replaced (the last one, if there are several).
+    /// Otherwise, the new binding will be appended to the end of the list.
+    pub fn upsert(&mut self, binding: KeyBinding) -> Result<(), SpannedError> {
+        self.upsert_before(binding, None)
+    }
+
+    /// Like [`Self::upsert`], except that a new binding is inserted ahead of the first entry with a greater M/G position
+    /// (so that a file kept in M/G order remains that way).
+    pub fn upsert_in_order(&mut self, binding: KeyBinding) -> Result<(), SpannedError> {
+        let successor = self.entries.iter().position(|entry| (entry.binding.m, entry.binding.g) > (binding.m, binding.g));
+        self.upsert_before(binding, successor)
+    }
+
+    fn upsert_before(&mut self, binding: KeyBinding, successor: Option<usize>) -> Result<(), SpannedError> {
+        let existing = self.entries.iter().rposition(|entry| entry.binding.is_at(binding.m, binding.g, binding.on));
+
+        let mut text = std::mem::take(&mut self.text);
+        if let Some(existing) = existing {
+            let span = self.entries[existing].span.clone();
+            let rendered = render(&binding, indentation_before(&text, span.start));
+            text.replace_range(span, &rendered);
+        }
+        else if let Some(successor) = successor.map(|index| &self.entries[index]) {
+            let indent = indentation_before(&text, successor.span.start).to_owned();
+            let rendered = render(&binding, &indent);
+            if indent.len() == successor.span.start - line_start(&text, successor.span.start) {
+                //Keep any comments that lead into the successor attached to it
+                text.insert_str(leading_comments_start(&text, line_start(&text, successor.span.start)), &format!("{indent}{rendered},\n"));
+            } else {
+                text.insert_str(successor.span.start, &format!("{rendered}, "));
+            }
+        }
+        else {
+            let indent = self.entries.last()
+                .map_or(DEFAULT_INDENT, |last| indentation_before(&text, last.span.start))
+                .to_owned();
+            let rendered = render(&binding, &indent);
+
+            //Working back to front, so that earlier offsets remain valid:
+            match self.list_end {
+                Some(list_end) => {
+                    let closing_indent = indentation_before(&text, list_end).len();
+                    if closing_indent == list_end - line_start(&text, list_end) {
+                        text.insert_str(list_end - closing_indent, &format!("{indent}{rendered},\n"));
+                    } else {
+                        text.insert_str(list_end, &format!("\n{indent}{rendered},\n"));
+                    }
+                }
+                None => {
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("{indent}{rendered},\n"));
+                }
+            }
+            if let Some(last) = self.entries.last().filter(|last| last.comma.is_none()) {
+                text.insert(last.span.end, ',');
+            }
+        }
+
+        *self = Self::parse(text)?;
+        Ok(())
+    }
+
+    /// Removes every binding with the given coordinates, returning how many were removed.
+    /// Lines left empty by a removal are removed as well.
+    pub fn remove(&mut self, m: u8, g: u8, on: Direction) -> Result<usize, SpannedError> {
+        let mut text = std::mem::take(&mut self.text);
+        let mut removed = 0;
+        for entry in self.entries.iter().rev().filter(|entry| entry.binding.is_at(m, g, on)) {
+            let mut range = entry.span.start..entry.comma.map_or(entry.span.end, |comma| comma + 1);
+            let line_end = text[range.end..].find('\n').map_or(text.len(), |newline| range.end + newline + 1);
+            if indentation_before(&text, range.start).len() == range.start - line_start(&text, range.start)
+              && text[range.end..line_end].trim().is_empty() {
+                range = line_start(&text, range.start)..line_end;
+            }
+            text.replace_range(range, "");
+            removed += 1;
+        }
+
+        *self = Self::parse(text)?;
+        Ok(removed)
+    }
+}
+
+/// Serializes a single binding, indenting all but the first line
+fn render(binding: &KeyBinding, indent: &str) -> String {
+    ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME | Extensions::EXPLICIT_STRUCT_NAMES)
+        .to_string_pretty(binding, PrettyConfig::default())
+        .expect("KeyBinding is always serializable")
+        .replace('\n', &format!("\n{indent}"))
+}
+
+fn line_start(text: &str, offset: usize) -> usize {
+    text[..offset].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+/// The whitespace between the start of the line and the given offset (empty if anything else precedes it)
+fn indentation_before(text: &str, offset: usize) -> &str {
+    let preceding = &text[line_start(text, offset)..offset];
+    if preceding.chars().all(|c| c == ' ' || c == '\t') { preceding } else { "" }
+}
+
+/// Walks back from the given line start over any lines that consist solely of a `//` comment
+fn leading_comments_start(text: &str, mut offset: usize) -> usize {
+    while offset > 0 {
+        let previous_line = line_start(text, offset - 1);
+        if !text[previous_line..offset].trim_start().starts_with("//") {
+            break;
+        }
+        offset = previous_line;
+    }
+    offset
+}
+
+fn position_of(text: &str, offset: usize) -> Position {
+    Position {
+        line: text[..offset].matches('\n').count() + 1,
+        col: text[line_start(text, offset)..offset].chars().count() + 1,
+    }
+}
+
+/// Translates an error within an entry into a position within the whole text
+fn offset_error(mut err: SpannedError, entry_start: Position) -> SpannedError {
+    if err.position.line == 1 {
+        err.position.col += entry_start.col - 1;
+    }
+    err.position.line += entry_start.line - 1;
+    err
+}
+
+struct Layout {
+    /// Byte range of each entry, and the offset of the comma that follows it (if any)
+    entries: Vec<(Range<usize>, Option<usize>)>,
+    list_end: Option<usize>,
+}
+
+/// Walks over the text, skipping comments/strings/chars, to find the boundaries of each top-level entry
+fn scan(text: &str) -> Result<Layout, SpannedError> {
+    let bytes = text.as_bytes();
+    let error = |offset: usize, message: &str| SpannedError {
+        code: ron::Error::Message(message.into()),
+        position: position_of(text, offset),
+    };
+
+    let mut layout = Layout { entries: vec![], list_end: None };
+    let mut depth = 0_usize;
+    //The depth at which entries are found (only known once the first token is seen; 0 if the open bracket is missing)
+    let mut entry_depth: Option<usize> = None;
+    let mut entry: Option<Range<usize>> = None;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        let token_start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => { i += 1; continue; }
+            b'/' if next == Some(b'/') => {
+                i = text[i..].find('\n').map_or(bytes.len(), |newline| i + newline);
+                continue;
+            }
+            b'/' if next == Some(b'*') => {
+                let mut nesting = 0_usize;
+                loop {
+                    match (bytes.get(i), bytes.get(i + 1)) {
+                        (Some(b'/'), Some(b'*')) => { nesting += 1; i += 2; }
+                        (Some(b'*'), Some(b'/')) => { nesting -= 1; i += 2; if nesting == 0 { break; } }
+                        (Some(_), _) => i += 1,
+                        (None, _) => return Err(error(token_start, "unterminated block comment")),
+                    }
+                }
+                continue;
+            }
+            b'#' if next == Some(b'!') && depth == 0 => {
+                i = text[i..].find(']').map(|close| i + close + 1)
+                    .ok_or_else(|| error(token_start, "unterminated attribute"))?;
+                continue;
+            }
+            b'"' => i = skip_string(bytes, i + 1).ok_or_else(|| error(token_start, "unterminated string"))?,
+            b'r' if matches!(next, Some(b'"' | b'#')) && bytes[i + 1..].iter().find(|b| **b != b'#') == Some(&b'"') => {
+                let hashes = bytes[i + 1..].iter().take_while(|b| **b == b'#').count();
+                let terminator = format!("\"{}", "#".repeat(hashes));
+                let content_start = i + 1 + hashes + 1;
+                i = text[content_start..].find(&terminator).map(|end| content_start + end + terminator.len())
+                    .ok_or_else(|| error(token_start, "unterminated raw string"))?;
+            }
+            b'\'' => {
+                i = if next == Some(b'\\') { text[i + 3..].find('\'').map(|end| i + 3 + end + 1) }
+                    else { text[i + 1..].chars().next().map(|c| i + 1 + c.len_utf8() + 1).filter(|end| bytes.get(end - 1) == Some(&b'\'')) }
+                    .ok_or_else(|| error(token_start, "malformed char"))?;
+            }
+            b'[' if depth == 0 && entry_depth.is_none() => {
+                entry_depth = Some(1);
+                depth = 1;
+                i += 1;
+                continue;
+            }
+            b']' if depth == 1 && entry_depth == Some(1) => {
+                if let Some(span) = entry.take() {
+                    layout.entries.push((span, None));
+                }
+                layout.list_end = Some(i);
+                depth = 0;
+                i += 1;
+                continue;
+            }
+            b',' if Some(depth) == entry_depth => {
+                let span = entry.take().ok_or_else(|| error(token_start, "unexpected comma"))?;
+                layout.entries.push((span, Some(i)));
+                i += 1;
+                continue;
+            }
+            b'(' | b'[' | b'{' => { depth += 1; i += 1; }
+            b')' | b']' | b'}' => {
+                depth = depth.checked_sub(1)
+                    .ok_or_else(|| error(token_start, "unbalanced brackets"))?;
+                i += 1;
+            }
+            _ => i += 1,
+        }
+
+        //Any remaining token is significant, so must belong to an entry:
+        if layout.list_end.is_some() {
+            return Err(error(token_start, "unexpected content after the end of the list"));
+        }
+        let entry_depth = *entry_depth.get_or_insert(0);
+        if depth < entry_depth {
+            return Err(error(token_start, "unbalanced brackets"));
+        }
+        entry.get_or_insert(token_start..i).end = i;
+    }
+
+    let expected_depth = if layout.list_end.is_some() { 0 } else { entry_depth.unwrap_or(0) };
+    if depth != expected_depth {
+        return Err(error(bytes.len(), "unbalanced brackets"));
+    }
+    if let Some(span) = entry {
+        layout.entries.push((span, None));
+    }
+    Ok(layout)
+}
+
+/// Returns the offset just past the closing quote
+fn skip_string(bytes: &[u8], mut i: usize) -> Option<usize> {
+    loop {
+        match bytes.get(i)? {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Direction::*;
+    use crate::steps::Step;
+    use super::*;
+
+    fn binding(m: u8, g: u8, text: &str) -> KeyBinding {
+        KeyBinding::new(m, g, Press, vec![Step::Text(text.into())])
+    }
+
+    const ORIGINAL: &str = r#"#![enable(explicit_struct_names, implicit_some)]
+/* A comment with (unbalanced brackets] */
+[
+    //G1 is important
+    KeyBinding(
+        m: 1,
+        g: 1,
+        on: Press,
+        script: [
+            Text("a string with ) and ] in it"),
+            Key(Unicode(')'), Click),
+        ],
+    ),
+
+    KeyBinding(m: 2, g: 5, on: Release, script: [Key(Unicode('\''), Click)]),
+]
+//Trailing comment
+"#;
+
+    #[test]
+    fn locates_entries_around_comments_strings_and_chars() {
+        let document = BindingsDocument::parse(ORIGINAL.into()).expect("parses");
+
+        assert_eq!(
+            document.bindings().map(|binding| (binding.m, binding.g, binding.on)).collect::<Vec<_>>(),
+            vec![(1, 1, Press), (2, 5, Release)],
+        );
+    }
+
+    #[test]
+    fn replaces_only_the_matching_entry() {
+        let mut document = BindingsDocument::parse(ORIGINAL.into()).expect("parses");
+        document.upsert(KeyBinding { on: Release, ..binding(2, 5, "new") }).expect("upserts");
+
+        let expected = ORIGINAL.replace(
+            r"KeyBinding(m: 2, g: 5, on: Release, script: [Key(Unicode('\''), Click)])",
+            "KeyBinding(\n        m: 2,\n        g: 5,\n        on: Release,\n        script: [\n            Text(\"new\"),\n        ],\n    )",
+        );
+        assert_eq!(document.as_str(), expected);
+    }
+
+    #[test]
+    fn appends_new_entries_before_the_close_bracket() {
+        let mut document = BindingsDocument::parse(ORIGINAL.into()).expect("parses");
+        document.upsert(binding(3, 18, "new")).expect("upserts");
+
+        assert!(document.as_str().starts_with(&ORIGINAL[..ORIGINAL.find("]\n//Trailing").unwrap()]));
+        assert!(document.as_str().ends_with("        ],\n    ),\n]\n//Trailing comment\n"));
+        assert_eq!(document.bindings().last(), Some(&binding(3, 18, "new")));
+        assert_eq!(
+            ron::from_str::<Vec<KeyBinding>>(document.as_str()).expect("still valid").len(),
+            3,
+        );
+    }
+
+    #[test]
+    fn adds_missing_comma_when_appending() {
+        let mut document = BindingsDocument::parse("[KeyBinding(m: 1, g: 1, on: Press, script: [])]".into()).expect("parses");
+        document.upsert(binding(1, 2, "new")).expect("upserts");
+
+        assert_eq!(document.bindings().count(), 2);
+        assert!(document.as_str().starts_with("[KeyBinding(m: 1, g: 1, on: Press, script: []),\n"));
+    }
+
+    #[test]
+    fn appends_to_new_file_from_stub() {
+        let mut document = BindingsDocument::parse(include_str!("config_stub_record.ron").into()).expect("parses");
+        document.upsert(binding(1, 2, "first")).expect("upserts");
+        document.upsert(binding(1, 3, "second")).expect("upserts");
+        document.upsert(binding(1, 2, "replaced")).expect("upserts");
+
+        assert!(document.as_str().starts_with(include_str!("config_stub_record.ron").trim_end_matches("[\n]\n")));
+        assert_eq!(
+            ron::from_str::<Vec<KeyBinding>>(document.as_str()).expect("still valid"),
+            vec![binding(1, 2, "replaced"), binding(1, 3, "second")],
+        );
+    }
+
+    #[test]
+    fn tolerant_of_missing_brackets() {
+        let mut document = BindingsDocument::parse("KeyBinding(m: 1, g: 1, on: Press, script: [])\n".into()).expect("parses");
+        document.upsert(binding(1, 2, "new")).expect("upserts");
+
+        assert_eq!(document.bindings().count(), 2);
+    }
+
+    #[test]
+    fn reports_errors_relative_to_whole_text() {
+        let err = BindingsDocument::parse("[\n    KeyBinding(m: 1, g: 1, on: Pres, script: []),\n]".into()).expect_err("does not parse");
+
+        assert_eq!(err.position.line, 2);
+    }
+
+    #[test]
+    fn inserts_in_order_ahead_of_leading_comments() {
+        let mut document = BindingsDocument::parse(ORIGINAL.into()).expect("parses");
+        document.upsert_in_order(binding(1, 18, "between")).expect("upserts");
+
+        let text = document.as_str();
+        assert_eq!(
+            document.bindings().map(|binding| (binding.m, binding.g)).collect::<Vec<_>>(),
+            vec![(1, 1), (1, 18), (2, 5)],
+        );
+        assert!(text.contains("    ),\n\n    KeyBinding(\n        m: 1,\n        g: 18,"));
+        assert!(text.contains("    ),\n    KeyBinding(m: 2, g: 5"));
+
+        document.upsert_in_order(binding(1, 0, "first")).expect("upserts");
+        assert!(document.as_str().contains("[\n    KeyBinding(\n        m: 1,\n        g: 0,"));
+        assert!(document.as_str().contains("    ),\n    //G1 is important\n    KeyBinding(\n        m: 1,\n        g: 1,"));
+    }
+
+    #[test]
+    fn removes_entries_and_their_lines() {
+        let mut document = BindingsDocument::parse(ORIGINAL.into()).expect("parses");
+
+        assert_eq!(document.remove(2, 5, Release).expect("removes"), 1);
+        assert_eq!(document.remove(2, 5, Release).expect("removes"), 0);
+
+        assert_eq!(
+            document.as_str(),
+            ORIGINAL.replace("    KeyBinding(m: 2, g: 5, on: Release, script: [Key(Unicode('\\''), Click)]),\n", ""),
+        );
+    }
+
+    #[test]
+    fn renders_only_the_optional_fields_that_are_set() {
+        let named = KeyBinding { name: Some("greet".into()), description: Some("Says hi".into()), ..binding(1, 2, "hi") };
+
+        assert_eq!(render(&binding(1, 2, "hi"), "").replace(['\n', ' '], ""), r#"KeyBinding(m:1,g:2,on:Press,script:[Text("hi"),],)"#);
+        assert_eq!(
+            render(&named, "").replace(['\n', ' '], ""),
+            r#"KeyBinding(m:1,g:2,on:Press,name:"greet",description:"Sayshi",script:[Text("hi"),],)"#,
+        );
+        assert_eq!(named.describe(), "greet (M1 G2, on Press)");
+        assert_eq!(binding(1, 2, "hi").describe(), "M1 G2 (on Press)");
+    }
+
+    #[test]
+    fn inherits_only_what_is_missing() {
+        let replaced = KeyBinding { name: Some("greet".into()), label: Some("Hi".into()), ..binding(1, 2, "hi") };
+        let recorded = KeyBinding { label: Some("Hello".into()), ..binding(1, 2, "hello") };
+
+        assert_eq!(
+            recorded.inheriting_from(replaced),
+            KeyBinding { name: Some("greet".into()), label: Some("Hello".into()), ..binding(1, 2, "hello") },
+        );
+    }
+}
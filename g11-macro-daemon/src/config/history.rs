@@ -0,0 +1,132 @@
+//! The history of macros recorded on the fly, which allows each recording to be undone.
+//!
+//! For every recording (oldest first), the recorded binding that it replaced is remembered (along with its name, description and label).
+//! This is kept in the [`XDG_STATE_RECORDING_HISTORY`](super::XDG_STATE_RECORDING_HISTORY) file,
+//! under `$XDG_STATE_HOME` (usually `~/.local/state`), since it is not something the user is expected to edit.
+
+use std::{fs, io, path::Path};
+use enigo::Direction;
+use serde::{Deserialize, Serialize};
+use ron::ser::PrettyConfig;
+use crate::steps::Step;
+use super::{backup, KeyBinding, LoadError};
+
+/// How many recordings are remembered (the oldest being forgotten first)
+pub const HISTORY_LENGTH: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub m: u8,
+    pub g: u8,
+    pub on: Direction,
+    /// The recorded binding that was replaced (if there was one)
+    pub replaced: Option<KeyBinding>,
+}
+
+/// An entry as saved, which (in histories saved by earlier versions) may only have the replaced binding's script
+#[derive(Deserialize)]
+struct SavedEntry {
+    m: u8,
+    g: u8,
+    on: Direction,
+    #[serde(default)]
+    replaced: Option<KeyBinding>,
+    #[serde(default)]
+    previous: Option<Vec<Step>>,
+}
+impl From<SavedEntry> for HistoryEntry {
+    fn from(SavedEntry { m, g, on, replaced, previous }: SavedEntry) -> Self {
+        let replaced = replaced.or_else(|| previous.map(|script| KeyBinding::new(m, g, on, script)));
+        Self { m, g, on, replaced }
+    }
+}
+
+/// Reads the history, which is empty if the file does not yet exist
+pub fn load(path: &Path) -> Result<Vec<HistoryEntry>, LoadError> {
+    match fs::read_to_string(path) {
+        Ok(text) => ron::from_str::<Vec<SavedEntry>>(&text)
+            .map(|entries| entries.into_iter().map(HistoryEntry::from).collect())
+            .map_err(|err| LoadError::unable_to_parse_or_load_config(path.into(), err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(LoadError::Loading(path.into(), err)),
+    }
+}
+
+/// Replaces the history file, keeping only the most recent [`HISTORY_LENGTH`] entries
+pub fn save(path: &Path, history: &[HistoryEntry]) -> io::Result<()> {
+    let recent = &history[history.len().saturating_sub(HISTORY_LENGTH)..];
+    let text = ron::ser::to_string_pretty(recent, PrettyConfig::default()).map_err(io::Error::other)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    backup::replace_with_backups(path, text.as_bytes(), 0)
+}
+
+/// Removes and returns the most recent entry (at the given M/G coordinates, if any)
+pub fn take_latest(history: &mut Vec<HistoryEntry>, coordinates: Option<(u8, u8)>) -> Option<HistoryEntry> {
+    history.iter()
+        .rposition(|entry| coordinates.is_none_or(|(m, g)| entry.m == m && entry.g == g))
+        .map(|index| history.remove(index))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::{Direction::*, Key};
+    use super::*;
+
+    fn entry(m: u8, g: u8, previous: Option<Vec<Step>>) -> HistoryEntry {
+        HistoryEntry { m, g, on: Press, replaced: previous.map(|script| KeyBinding::new(m, g, Press, script)) }
+    }
+
+    #[test]
+    fn takes_the_latest_entry() {
+        let mut history = vec![
+            entry(1, 1, None),
+            entry(1, 2, Some(vec![Step::Key(Key::Tab, Click)])),
+            entry(1, 1, Some(vec![Step::Text("old".into())])),
+        ];
+
+        assert_eq!(take_latest(&mut history, Some((1, 2))), Some(entry(1, 2, Some(vec![Step::Key(Key::Tab, Click)]))));
+        assert_eq!(take_latest(&mut history, None), Some(entry(1, 1, Some(vec![Step::Text("old".into())]))));
+        assert_eq!(take_latest(&mut history, Some((2, 1))), None);
+        assert_eq!(history, vec![entry(1, 1, None)]);
+    }
+
+    #[test]
+    fn saves_only_the_most_recent_entries() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("nested").join("recording_history.ron");
+        assert_eq!(load(&path).expect("missing file is empty"), vec![]);
+
+        let history: Vec<_> = (0..HISTORY_LENGTH + 2).map(|g| entry(1, g as u8, None)).collect();
+        save(&path, &history).expect("can save");
+
+        assert_eq!(load(&path).expect("can load"), history[2..]);
+    }
+
+    #[test]
+    fn keeps_the_replaced_binding_whole() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("recording_history.ron");
+        let named = KeyBinding {
+            name: Some("close-tab".into()),
+            description: Some("Closes the current tab".into()),
+            label: Some("Close".into()),
+            ..KeyBinding::new(1, 13, Press, vec![Step::Key(Key::Unicode('w'), Click)])
+        };
+        let history = vec![HistoryEntry { m: 1, g: 13, on: Press, replaced: Some(named) }, entry(1, 2, None)];
+        save(&path, &history).expect("can save");
+
+        assert_eq!(load(&path).expect("can load"), history);
+    }
+
+    #[test]
+    fn loads_histories_of_scripts_alone() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let path = dir.path().join("recording_history.ron");
+        fs::write(&path, "[(m: 1, g: 2, on: Press, previous: Some([Key(Tab, Click)])), (m: 1, g: 3, on: Press, previous: None)]").expect("can write");
+
+        assert_eq!(load(&path).expect("can load"), vec![entry(1, 2, Some(vec![Step::Key(Key::Tab, Click)])), entry(1, 3, None)]);
+    }
+}
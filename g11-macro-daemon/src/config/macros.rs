@@ -0,0 +1,259 @@
+//! Named macros, kept in the [`XDG_CONFIG_MACROS`](super::XDG_CONFIG_MACROS) file,
+//! which may be run from any script by a [`Step::Call`] (and so edited in one place).
+//!
+//! A macro may also serve as a template: each `{parameter}` within its `Text` steps and program arguments
+//! is replaced with the value given for it by the call (e.g. `Call("jira-comment", {"ticket": "OPS-12"})`).
+
+use std::collections::{BTreeMap, BTreeSet};
+use derive_more::{Display, Error};
+use log::warn;
+use crate::steps::{placeholders, Step};
+use super::KeyBinding;
+
+/// The script of each macro, by name
+pub type Macros = BTreeMap<String, Vec<Step>>;
+/// The values given to a macro's parameters, by name
+pub type Parameters = BTreeMap<String, String>;
+
+/// Checks that every macro called (whether by a binding or by another macro) is defined, and is given exactly the parameters it uses,
+/// and that no macro ends up calling itself
+pub fn validate(macros: &Macros, key_bindings: &[KeyBinding]) -> Result<(), MacroError> {
+    let mut checked = BTreeSet::new();
+    for (name, script) in macros {
+        if !checked.contains(name.as_str()) {
+            check_calls(macros, script, &format!("macro {name:?}"), &mut vec![name.clone()], &mut checked)?;
+            checked.insert(name.as_str());
+        }
+    }
+    for binding in key_bindings {
+        check_calls(macros, &binding.script, &binding.describe(), &mut vec![], &mut checked)?;
+    }
+    Ok(())
+}
+
+/// Walks the calls made by the script, depth first, with `stack` holding the macros being called (innermost last).
+/// The calls made by the macros in `checked` are already known to be valid (and so are not walked again).
+fn check_calls<'a>(macros: &'a Macros, script: &[Step], caller: &str, stack: &mut Vec<String>, checked: &mut BTreeSet<&'a str>) -> Result<(), MacroError> {
+    for (name, parameters) in calls(script) {
+        if let Some(start) = stack.iter().position(|called| called == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.clone());
+            return Err(MacroError::Cycle(cycle));
+        }
+        let (name, script) = macros.get_key_value(name).ok_or_else(|| MacroError::Undefined(name.clone(), caller.into()))?;
+
+        let used = parameters_used(script);
+        if let Some(missing) = used.iter().find(|parameter| !parameters.contains_key(**parameter)) {
+            return Err(MacroError::MissingParameter(name.clone(), missing.to_string(), caller.into()));
+        }
+        if let Some(unknown) = parameters.keys().find(|parameter| !used.contains(parameter.as_str())) {
+            return Err(MacroError::UnknownParameter(name.clone(), unknown.clone(), caller.into()));
+        }
+
+        if checked.contains(name.as_str()) {
+            continue;
+        }
+        stack.push(name.clone());
+        check_calls(macros, script, &format!("macro {name:?}"), stack, checked)?;
+        stack.pop();
+        checked.insert(name.as_str());
+    }
+    Ok(())
+}
+
+fn calls(script: &[Step]) -> impl Iterator<Item = (&String, &Parameters)> {
+    script.iter().filter_map(|step| match step {
+        Step::Call(name, parameters) => Some((name, parameters)),
+        _ => None,
+    })
+}
+
+/// The names of the parameters within the script (including those within the parameters it passes on to other macros)
+fn parameters_used(script: &[Step]) -> BTreeSet<&str> {
+    script.iter()
+        .flat_map(|step| -> Box<dyn Iterator<Item = &String> + '_> {
+            match step {
+                Step::Text(text) => Box::new([text].into_iter()),
+                Step::Run(program) => Box::new(program.args().iter()),
+                Step::Call(_, parameters) => Box::new(parameters.values()),
+                _ => Box::new([].into_iter()),
+            }
+        })
+        .flat_map(|text| placeholders(text))
+        .collect()
+}
+
+/// The name within each `{parameter}` of the text
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    placeholders::names(text).into_iter().filter(|name| is_parameter_name(name))
+}
+
+/// Lest braces used for other purposes be mistaken for parameters (e.g. when typing code),
+/// or the [placeholders] expanded when the step is executed (e.g. `{hostname}`)
+fn is_parameter_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') && !placeholders::is_builtin(name)
+}
+
+/// Replaces each `{parameter}` within the text with its value (leaving those without a value as-is)
+fn substitute(text: &str, parameters: &Parameters) -> String {
+    placeholders::replace(text, |name| parameters.get(name).filter(|_| is_parameter_name(name)).cloned())
+}
+
+fn substitute_step(step: Step, parameters: &Parameters) -> Step {
+    match step {
+        Step::Text(text) => Step::Text(substitute(&text, parameters)),
+        Step::Run(program) => Step::Run(program.map_args(|arg| substitute(&arg, parameters))),
+        Step::Call(name, passed) => Step::Call(name, passed.into_iter().map(|(parameter, value)| (parameter, substitute(&value, parameters))).collect()),
+        step => step,
+    }
+}
+
+/// Replaces each [`Step::Call`] with the script of the macro called (recursively), with its parameters substituted.
+/// Calls to undefined macros (or cycles, which [`validate`] would have rejected) are left out, with a warning.
+pub fn expand(script: impl IntoIterator<Item = Step>, macros: &Macros) -> Vec<Step> {
+    fn expand_into(expanded: &mut Vec<Step>, script: Vec<Step>, macros: &Macros, stack: &mut Vec<String>) {
+        for step in script {
+            match step {
+                Step::Call(name, _) if stack.contains(&name) => warn!("Not calling macro {name:?} from within itself"),
+                Step::Call(name, parameters) => match macros.get(&name) {
+                    Some(script) => {
+                        stack.push(name);
+                        let script = script.iter().map(|step| substitute_step(step.clone(), &parameters)).collect();
+                        expand_into(expanded, script, macros, stack);
+                        stack.pop();
+                    }
+                    None => warn!("Not calling macro {name:?}, since it is not defined"),
+                },
+                step => expanded.push(step),
+            }
+        }
+    }
+
+    let mut expanded = vec![];
+    expand_into(&mut expanded, script.into_iter().collect(), macros, &mut vec![]);
+    expanded
+}
+
+#[derive(Debug, Display, Error)]
+pub enum MacroError {
+    #[display("There is no macro named {_0:?} (as called by {_1})")]
+    Undefined(#[error(not(source))] String, #[error(not(source))] String),
+    #[display("Macros must not call themselves, but these do: {}", _0.join(" → "))]
+    Cycle(#[error(not(source))] Vec<String>),
+    #[display("Macro {_0:?} needs a value for its parameter {_1:?}, but {_2} does not give one")]
+    MissingParameter(#[error(not(source))] String, #[error(not(source))] String, #[error(not(source))] String),
+    #[display("Macro {_0:?} has no parameter {_1:?}, yet {_2} gives one")]
+    UnknownParameter(#[error(not(source))] String, #[error(not(source))] String, #[error(not(source))] String),
+}
+
+#[cfg(test)]
+mod tests {
+    use enigo::Direction::*;
+    use crate::steps::program::Program;
+    use super::*;
+
+    fn defined(entries: &[(&str, Vec<Step>)]) -> Macros {
+        entries.iter().map(|(name, script)| (name.to_string(), script.clone())).collect()
+    }
+
+    fn call(name: &str) -> Step {
+        Step::Call(name.into(), Parameters::new())
+    }
+
+    fn call_with(name: &str, parameters: &[(&str, &str)]) -> Step {
+        Step::Call(name.into(), parameters.iter().map(|(parameter, value)| (parameter.to_string(), value.to_string())).collect())
+    }
+
+    fn text(text: &str) -> Step {
+        Step::Text(text.into())
+    }
+
+    #[test]
+    fn expands_nested_calls() {
+        let macros = defined(&[("greet", vec![text("hi "), call("name")]), ("name", vec![text("there")])]);
+
+        assert_eq!(validate(&macros, &[KeyBinding::new(1, 1, Press, vec![call("greet")])]).ok(), Some(()));
+        assert_eq!(
+            expand(vec![call("greet"), text("!"), call("name")], &macros),
+            vec![text("hi "), text("there"), text("!"), text("there")],
+        );
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let macros = defined(&[("a", vec![call("b")]), ("b", vec![text("x"), call("c")]), ("c", vec![call("a")]), ("d", vec![call("d")])]);
+
+        match validate(&macros, &[]) {
+            Err(MacroError::Cycle(cycle)) => assert_eq!(cycle, ["a", "b", "c", "a"]),
+            other => panic!("Expected a cycle, but got {other:?}"),
+        }
+        assert!(matches!(validate(&defined(&[("d", vec![call("d")])]), &[]), Err(MacroError::Cycle(_))));
+        assert_eq!(expand(vec![call("d")], &macros), vec![]);
+    }
+
+    #[test]
+    fn checks_shared_macros_once() {
+        //Each level calls the next twice, so walking every path would take 2^64 calls
+        let mut macros: Macros = (0..64).map(|level| (format!("m{level}"), vec![call(&format!("m{}", level + 1)), call(&format!("m{}", level + 1))])).collect();
+        macros.insert("m64".into(), vec![text("x")]);
+
+        assert_eq!(validate(&macros, &[KeyBinding::new(1, 1, Press, vec![call("m0")])]).ok(), Some(()));
+        macros.insert("m64".into(), vec![call("m32")]);
+        assert!(matches!(validate(&macros, &[]), Err(MacroError::Cycle(_))));
+    }
+
+    #[test]
+    fn rejects_undefined_macros() {
+        let binding = KeyBinding { name: Some("greeting".into()), ..KeyBinding::new(1, 1, Press, vec![call("greet")]) };
+
+        assert!(matches!(validate(&Macros::new(), &[binding]), Err(MacroError::Undefined(name, by)) if name == "greet" && by.starts_with("greeting")));
+        assert!(matches!(validate(&defined(&[("a", vec![call("b")])]), &[]), Err(MacroError::Undefined(name, _)) if name == "b"));
+        assert_eq!(expand(vec![text("x"), call("greet")], &Macros::new()), vec![text("x")]);
+    }
+
+    #[test]
+    fn parses_a_macros_file() {
+        let macros: Macros = ron::from_str(r#"{
+            "close-tab": [Key(Control, Press), Key(Unicode('w'), Click), Key(Control, Release)],
+            "close-two-tabs": [Call("close-tab"), Call("close-tab")],
+        }"#).expect("parses");
+
+        assert_eq!(macros.len(), 2);
+        assert_eq!(validate(&macros, &[]).ok(), Some(()));
+        assert_eq!(expand(vec![call("close-two-tabs")], &macros).len(), 6);
+    }
+
+    #[test]
+    fn substitutes_parameters() {
+        let macros = defined(&[
+            ("jira-comment", vec![text("Re {ticket}: {body} {not a parameter} {} on {hostname} {{body}}"), call_with("open", &[("url", "https://jira/{ticket}")])]),
+            ("open", vec![Step::Run(Program::new("xdg-open".into(), vec!["{url}".into()]))]),
+        ]);
+        let binding = KeyBinding::new(1, 1, Press, vec![call_with("jira-comment", &[("ticket", "OPS-12"), ("body", "{done}")])]);
+
+        assert_eq!(validate(&macros, std::slice::from_ref(&binding)).ok(), Some(()));
+        assert_eq!(expand(binding.script, &macros), vec![
+            text("Re OPS-12: {done} {not a parameter} {} on {hostname} {{body}}"),
+            Step::Run(Program::new("xdg-open".into(), vec!["https://jira/OPS-12".into()])),
+        ]);
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_parameters() {
+        let macros = defined(&[("jira-comment", vec![text("Re {ticket}")])]);
+        let calling = |parameters: &[(&str, &str)]| [KeyBinding::new(1, 1, Press, vec![call_with("jira-comment", parameters)])];
+
+        assert!(matches!(validate(&macros, &calling(&[])), Err(MacroError::MissingParameter(_, parameter, _)) if parameter == "ticket"));
+        assert!(matches!(
+            validate(&macros, &calling(&[("ticket", "OPS-1"), ("tikcet", "OPS-1")])),
+            Err(MacroError::UnknownParameter(_, parameter, _)) if parameter == "tikcet",
+        ));
+    }
+
+    #[test]
+    fn serializes_calls_without_parameters_briefly() {
+        assert_eq!(ron::to_string(&call("close-tab")).expect("serializes"), r#"Call("close-tab")"#);
+        assert_eq!(ron::to_string(&call_with("a", &[("b", "c")])).expect("serializes"), r#"Call("a",{"b":"c"})"#);
+        assert_eq!(ron::from_str::<Step>(r#"Call("close-tab")"#).expect("parses"), call("close-tab"));
+    }
+}
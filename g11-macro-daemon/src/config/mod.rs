@@ -0,0 +1,726 @@
+//! Manages the user configuration at rest, with file(s) stored
+//! in the `g11-macro-daemon` subdirectory of `$XDG_CONFIG` (usually `~/.config`)
+
+use std::{
+    fs::{self, File},
+    io::{self, Write, Read, BufRead, BufReader},
+    path::{PathBuf, Path},
+};
+use derive_more::{Display, Error};
+use enigo::Direction;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use ron::{
+    error::{Position, SpannedError},
+    extensions::Extensions,
+};
+use crate::{schema::EnigoDirectionDef, steps::Step};
+use document::BindingsDocument;
+
+mod document;
+mod backup;
+mod history;
+mod macros;
+mod settings;
+mod variables;
+
+pub use history::HistoryEntry;
+pub use macros::{expand as expand_macro_calls, MacroError, Macros};
+pub use settings::{KeyboardLayout, RecordSource, RecordTiming, RecordingSettings, Settings, XkbNames};
+pub use variables::{Value, Variables, VariablesSnapshot};
+
+#[derive(Default, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Config {
+    pub key_bindings: Vec<KeyBinding>,
+    pub macros: Macros,
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct KeyBinding {
+    /// The `M` key (numbered `1 ..= 3`) whose bank must be active for this binding to apply
+    #[schemars(range(min = 1, max = 3))]
+    pub m: u8,
+    /// The `G` key (numbered `1 ..= 18`)
+    #[schemars(range(min = 1, max = 18))]
+    pub g: u8,
+    /// If set to `Press`, will run the script as soon as the key is pressed. Otherwise, it will wait for release.
+    #[schemars(with = "EnigoDirectionDef")]
+    pub on: Direction,
+    /// Identifies the binding (e.g. `"close-tab"`) in logs, listings and diagnostics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// What the binding is for, in a sentence or so
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// A short legend for the key (e.g. `"Copy"`), as shown on exported cheat-sheets in place of a summary of the script
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The sequence of steps to be executed
+    pub script: Vec<Step>,
+}
+impl KeyBinding {
+    /// A binding without a name, description or label
+    pub fn new(m: u8, g: u8, on: Direction, script: Vec<Step>) -> Self {
+        Self { m, g, on, name: None, description: None, label: None, script }
+    }
+
+    /// Whether this binding applies to the given coordinates
+    pub fn is_at(&self, m: u8, g: u8, on: Direction) -> bool {
+        self.m == m && self.g == g && self.on == on
+    }
+
+    /// Identifies the binding for the user, e.g. `close-tab (M1 G13, on Press)` or just `M1 G13 (on Press)` if it has no name
+    pub fn describe(&self) -> String {
+        let Self { m, g, on, .. } = self;
+        match &self.name {
+            Some(name) => format!("{name} (M{m} G{g}, on {on:?})"),
+            None => format!("M{m} G{g} (on {on:?})"),
+        }
+    }
+
+    /// This binding, with the name, description and label of the one it replaces (wherever it has none of its own)
+    pub fn inheriting_from(self, replaced: KeyBinding) -> Self {
+        Self {
+            name: self.name.or(replaced.name),
+            description: self.description.or(replaced.description),
+            label: self.label.or(replaced.label),
+            ..self
+        }
+    }
+}
+
+pub const XDG_PREFIX: &str = "g11-macro-daemon";
+pub const XDG_CONFIG_KEY_BINDINGS: &str = "key_bindings.ron";
+pub const XDG_CONFIG_KEY_RECORDINGS: &str = "key_recordings.ron";
+pub const XDG_CONFIG_SETTINGS: &str = "settings.ron";
+pub const XDG_CONFIG_MACROS: &str = "macros.ron";
+pub const XDG_STATE_RECORDING_HISTORY: &str = "recording_history.ron";
+pub const XDG_STATE_VARIABLES: &str = "variables.ron";
+pub const XDG_CONFIG_LOCK: &str = ".lock";
+
+/// Serializes the read-modify-write of the [`XDG_CONFIG_KEY_BINDINGS`] and [`XDG_CONFIG_KEY_RECORDINGS`] files (and the history),
+/// which may be updated by more than one thread of the daemon (e.g. by the persistence worker and the web UI)
+/// as well as by other processes (e.g. the `edit`, `undo` and `promote` subcommands, or the TUI).
+/// The lock is released once the returned file is dropped.
+fn lock_config() -> Result<File, LoadError> {
+    let path = xdg::BaseDirectories::with_prefix(XDG_PREFIX).place_config_file(XDG_CONFIG_LOCK).map_err(LoadError::Locating)?;
+    backup::lock(&path).map_err(|err| LoadError::Loading(path, err))
+}
+
+/// Loads the [`XDG_CONFIG_KEY_BINDINGS`] file, creating an empty stub if it does not yet exist.
+/// Also loads the [`XDG_CONFIG_KEY_RECORDINGS`], [`XDG_CONFIG_MACROS`] and [`XDG_CONFIG_SETTINGS`] files, if they exist.
+///
+/// Every macro called must be defined, and no macro may (however indirectly) call itself ([`LoadError::InvalidMacros`]).
+pub fn ensure_and_load_config_file() -> Result<Config, LoadError> {
+    let app_config_dir = xdg::BaseDirectories::with_prefix(XDG_PREFIX);
+    let key_bindings_path = app_config_dir.place_config_file(XDG_CONFIG_KEY_BINDINGS).map_err(LoadError::Locating)?;
+    let key_recordings_path = app_config_dir.find_config_file(XDG_CONFIG_KEY_RECORDINGS);
+    let settings_path = app_config_dir.find_config_file(XDG_CONFIG_SETTINGS);
+    let macros_path = app_config_dir.find_config_file(XDG_CONFIG_MACROS);
+
+    let mut key_bindings =
+        if key_bindings_path.try_exists().map_err(LoadError::Locating)? {
+            try_load_key_bindings(&key_bindings_path, || File::open(&key_bindings_path), None, false)?
+        } else { //Try to create a default file with instructions/samples
+            File::create_new(&key_bindings_path)
+                .and_then(|mut file| file.write_all(include_bytes!("config_stub.ron")))
+                .inspect_err(|err| warn!("Failed to create stub for key bindings file: {}. Ignoring...\n\tCause: {err:#?}", key_bindings_path.display()))
+                .map_or_else(|_| vec![], |_| vec![])
+        };
+
+    if let Some(key_recordings_path) = key_recordings_path {
+        key_bindings.extend(
+            try_load_key_bindings(&key_recordings_path, || File::open(&key_recordings_path), None, false)?
+        );
+    }
+
+    let settings = match settings_path {
+        Some(settings_path) =>
+            ron::Options::default()
+                .with_default_extension(Extensions::IMPLICIT_SOME)
+                .from_reader(File::open(&settings_path).map_err(|err| LoadError::Loading(settings_path.clone(), err))?)
+                .map_err(|err| LoadError::unable_to_parse_or_load_config(settings_path, err))?,
+        None => Settings::default(),
+    };
+
+    let macros = load_macros(macros_path)?;
+    macros::validate(&macros, &key_bindings).map_err(LoadError::InvalidMacros)?;
+
+    Ok(Config { key_bindings, macros, settings })
+}
+
+/// Reads the [`XDG_CONFIG_MACROS`] file, if it exists
+fn load_macros(macros_path: Option<PathBuf>) -> Result<Macros, LoadError> {
+    Ok(match macros_path {
+        Some(macros_path) =>
+            ron::Options::default()
+                .with_default_extension(Extensions::IMPLICIT_SOME)
+                .from_reader(File::open(&macros_path).map_err(|err| LoadError::Loading(macros_path.clone(), err))?)
+                .map_err(|err| LoadError::unable_to_parse_or_load_config(macros_path, err))?,
+        None => Macros::default(),
+    })
+}
+
+/// Checks the macro calls of bindings that are about to be saved (see [`macros::validate`]), lest the config then fail to load.
+/// The macros are only loaded if any of the bindings call one.
+fn validate_calls(bindings: &[KeyBinding], load_macros: impl FnOnce() -> Result<Macros, LoadError>) -> Result<(), LoadError> {
+    if !bindings.iter().any(|binding| binding.script.iter().any(|step| matches!(step, Step::Call(..)))) {
+        return Ok(());
+    }
+    macros::validate(&load_macros()?, bindings).map_err(LoadError::InvalidMacros)
+}
+
+/// [`validate_calls`] against the [`XDG_CONFIG_MACROS`] file
+pub fn validate_saved_calls(bindings: &[KeyBinding]) -> Result<(), LoadError> {
+    validate_calls(bindings, || load_macros(xdg::BaseDirectories::with_prefix(XDG_PREFIX).find_config_file(XDG_CONFIG_MACROS)))
+}
+
+/// Parse a key bindings file, being tolerant of one or both of the outer list brackets being absent
+/// TODO This is pretty hacky and gross. Would be more appropriate to just write a parser.
+fn try_load_key_bindings<R: Read>(
+    key_bindings_path: &Path,
+    key_bindings_file: impl Fn() -> io::Result<R>,
+    replace_missing_open_bracket: Option<Position>,
+    replace_missing_close_bracket: bool,
+) -> Result<Vec<KeyBinding>, LoadError> {
+    let mut outer_buf = String::new(); //Not terribly clean, but ensures that we don't run afoul of lifetimes.
+    let reader: Box<dyn Read> = {
+        let mut reader: Box<dyn Read> =
+            key_bindings_file()
+                .map(Box::new)
+                .map_err(|err| LoadError::Loading(key_bindings_path.into(), err))?;
+
+        if let Some(missing_open_bracket_pos) = replace_missing_open_bracket {
+            let mut lines = BufReader::new(reader).lines();
+            for line in lines.by_ref().take(missing_open_bracket_pos.line - 1) {
+                outer_buf.push_str(&line.map_err(|err| LoadError::Loading(key_bindings_path.into(), err))?);
+                outer_buf.push('\n');
+            }
+            outer_buf.push_str("\n[\n");
+            for line in lines {
+                outer_buf.push_str(&line.map_err(|err| LoadError::Loading(key_bindings_path.into(), err))?);
+                outer_buf.push('\n');
+            }
+            reader = Box::new(BufReader::new(outer_buf.as_bytes()));
+        }
+
+        if replace_missing_close_bracket {
+            reader = Box::new(reader.chain(BufReader::new(&b"\n]\n"[..])));
+        }
+
+        reader
+    };
+
+    ron::Options::default()
+        .from_reader(reader)
+        .or_else(|err| match (&err.code, replace_missing_open_bracket, replace_missing_close_bracket) {
+            (ron::Error::ExpectedArray, None, false) => {
+                warn!("Possibly missing the open bracket in {}; will attempt a more lenient parse, but you should fix the file.", key_bindings_path.display());
+                try_load_key_bindings(key_bindings_path, key_bindings_file, Some(err.position), false)
+                    .map_err(|_| LoadError::unable_to_parse_or_load_config(key_bindings_path.into(), err)) //If tolerant parse fails, just return the original error
+            }
+            (ron::Error::ExpectedStructName(name), _, false) if name == "KeyBinding" => {
+                warn!("Possibly missing the close bracket in {}; will attempt a more lenient parse, but you should fix the file.", key_bindings_path.display());
+                try_load_key_bindings(key_bindings_path, key_bindings_file, replace_missing_open_bracket, true)
+                    .map_err(|_| LoadError::unable_to_parse_or_load_config(key_bindings_path.into(), err)) //If tolerant parse fails, just return the original error
+            }
+            _ => Err(LoadError::unable_to_parse_or_load_config(key_bindings_path.into(), err))
+        })
+}
+
+/// Creates/updates the [`XDG_CONFIG_KEY_RECORDINGS`] file, adding the given binding.
+/// If an existing binding exists with the same coordinates, then it will be replaced.
+/// Otherwise, the new binding will be appended to the list.
+///
+/// Only the text of the affected binding is touched; comments/formatting elsewhere in the file are preserved.
+/// The file is replaced atomically, with the previous version kept as a backup (see [`restore_recorded_macros`]).
+///
+/// The recorded binding being replaced (if any) is remembered in the history, so that it may be restored by [`undo_recorded_macro`].
+///
+/// Refuses to save a binding whose macro calls are invalid ([`SaveRecordedMacroError::InvalidMacros`]).
+pub fn save_recorded_macro(new_key_binding: KeyBinding) -> Result<(), SaveRecordedMacroError> {
+    validate_saved_calls(std::slice::from_ref(&new_key_binding))?;
+    let _lock = lock_config().map_err(SaveRecordedMacroError::Loading)?;
+    let app_config_dir = xdg::BaseDirectories::with_prefix(XDG_PREFIX);
+    let key_recordings_path = app_config_dir.place_config_file(XDG_CONFIG_KEY_RECORDINGS)
+        .map_err(LoadError::Locating).map_err(SaveRecordedMacroError::Loading)?;
+
+    let mut document = load_document(&key_recordings_path, include_str!("config_stub_record.ron"))
+        .map_err(SaveRecordedMacroError::Loading)?;
+    let KeyBinding { m, g, on, .. } = new_key_binding;
+    let replaced = document.bindings().filter(|binding| binding.is_at(m, g, on)).last().cloned();
+    document.upsert(new_key_binding)
+        .map_err(|err| LoadError::unable_to_parse_or_load_config(key_recordings_path.clone(), err)).map_err(SaveRecordedMacroError::Loading)?;
+
+    backup::replace_with_backups(&key_recordings_path, document.as_str().as_bytes(), backup::BACKUP_COUNT)
+        .map_err(|err| SaveRecordedMacroError::Saving(key_recordings_path.clone(), err))?;
+
+    //The recording itself has been saved, so a failure here must not cause it to be retried
+    let _ = app_config_dir.place_state_file(XDG_STATE_RECORDING_HISTORY)
+        .map_err(LoadError::Locating)
+        .and_then(|history_path| {
+            let mut history = history::load(&history_path)?;
+            history.push(HistoryEntry { m, g, on, replaced });
+            history::save(&history_path, &history).map_err(|err| LoadError::Loading(history_path, err))
+        })
+        .inspect_err(|err| warn!("Unable to add M{m} G{g} to the recording history (so it cannot be undone): {err}"));
+    Ok(())
+}
+
+/// Undoes the most recent recording (at the given M/G coordinates, if any), as remembered in the history:
+/// the recorded binding it replaced is restored to the [`XDG_CONFIG_KEY_RECORDINGS`] file, or else the recording is simply removed.
+///
+/// Returns `None` if there is nothing to undo.
+pub fn undo_recorded_macro(coordinates: Option<(u8, u8)>) -> Result<Option<Undone>, UndoError> {
+    let _lock = lock_config()?;
+    let app_config_dir = xdg::BaseDirectories::with_prefix(XDG_PREFIX);
+    let history_path = app_config_dir.place_state_file(XDG_STATE_RECORDING_HISTORY).map_err(LoadError::Locating)?;
+    let key_recordings_path = app_config_dir.place_config_file(XDG_CONFIG_KEY_RECORDINGS).map_err(LoadError::Locating)?;
+
+    let mut history = history::load(&history_path)?;
+    let Some(HistoryEntry { m, g, on, replaced }) = history::take_latest(&mut history, coordinates) else { return Ok(None) };
+
+    let mut recordings = load_document(&key_recordings_path, include_str!("config_stub_record.ron"))?;
+    match replaced {
+        Some(binding) => recordings.upsert(binding),
+        None => recordings.remove(m, g, on).map(|_| ()),
+    }.map_err(|err| LoadError::unable_to_parse_or_load_config(key_recordings_path.clone(), err))?;
+
+    backup::replace_with_backups(&key_recordings_path, recordings.as_str().as_bytes(), backup::BACKUP_COUNT)
+        .map_err(|err| UndoError::Saving(key_recordings_path, err))?;
+    history::save(&history_path, &history)
+        .map_err(|err| UndoError::Saving(history_path, err))?;
+
+    //Without a recording, the static binding (if any) applies once more
+    let restored = match recordings.bindings().filter(|binding| binding.is_at(m, g, on)).last() {
+        Some(binding) => Some(binding.clone()),
+        None => {
+            let key_bindings_path = app_config_dir.place_config_file(XDG_CONFIG_KEY_BINDINGS).map_err(LoadError::Locating)?;
+            load_document(&key_bindings_path, include_str!("config_stub.ron"))?
+                .bindings().filter(|binding| binding.is_at(m, g, on)).last().cloned()
+        }
+    };
+    Ok(Some(Undone { m, g, on, restored }))
+}
+
+/// Lists the recordings that may be undone (oldest first)
+pub fn recording_history() -> Result<Vec<HistoryEntry>, LoadError> {
+    xdg::BaseDirectories::with_prefix(XDG_PREFIX)
+        .place_state_file(XDG_STATE_RECORDING_HISTORY)
+        .map_err(LoadError::Locating)
+        .and_then(|history_path| history::load(&history_path))
+}
+
+/// The result of [`undo_recorded_macro`]
+#[derive(Debug, Clone)]
+pub struct Undone {
+    pub m: u8,
+    pub g: u8,
+    pub on: Direction,
+    /// The binding that now applies at these coordinates (whether a previous recording or a static binding), if any
+    pub restored: Option<KeyBinding>,
+}
+
+/// Moves recorded bindings from the [`XDG_CONFIG_KEY_RECORDINGS`] file into the [`XDG_CONFIG_KEY_BINDINGS`] file,
+/// either those at the given M/G coordinates (for both press and release) or all of them.
+///
+/// New entries are placed among the static bindings in M/G order. Unless `overwrite` is set,
+/// this will refuse to replace any static binding that has a different script (returning [`PromoteError::WouldOverwrite`]).
+/// Returns the bindings that were promoted.
+pub fn promote_recorded_macros(coordinates: Option<(u8, u8)>, overwrite: bool) -> Result<Vec<KeyBinding>, PromoteError> {
+    let _lock = lock_config()?;
+    let app_config_dir = xdg::BaseDirectories::with_prefix(XDG_PREFIX);
+    let key_bindings_path = app_config_dir.place_config_file(XDG_CONFIG_KEY_BINDINGS).map_err(LoadError::Locating)?;
+    let key_recordings_path = app_config_dir.place_config_file(XDG_CONFIG_KEY_RECORDINGS).map_err(LoadError::Locating)?;
+
+    let mut recordings = load_document(&key_recordings_path, include_str!("config_stub_record.ron"))?;
+    let mut promoted: Vec<KeyBinding> = vec![];
+    for binding in recordings.bindings().filter(|binding| coordinates.is_none_or(|(m, g)| binding.m == m && binding.g == g)) {
+        //Where there are duplicates, the last one wins (as it does when loading)
+        promoted.retain(|earlier| !earlier.is_at(binding.m, binding.g, binding.on));
+        promoted.push(binding.clone());
+    }
+    if promoted.is_empty() {
+        return Err(PromoteError::NothingToPromote);
+    }
+
+    let mut bindings = load_document(&key_bindings_path, include_str!("config_stub.ron"))?;
+    if !overwrite {
+        let conflicts: Vec<KeyBinding> = bindings.bindings()
+            .filter(|existing| promoted.iter().any(|new| new.is_at(existing.m, existing.g, existing.on) && new.script != existing.script))
+            .cloned()
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(PromoteError::WouldOverwrite(conflicts));
+        }
+    }
+
+    for binding in &promoted {
+        bindings.upsert_in_order(binding.clone())
+            .map_err(|err| LoadError::unable_to_parse_or_load_config(key_bindings_path.clone(), err))?;
+        recordings.remove(binding.m, binding.g, binding.on)
+            .map_err(|err| LoadError::unable_to_parse_or_load_config(key_recordings_path.clone(), err))?;
+    }
+
+    //Static bindings first: should the second write fail, the binding is merely duplicated rather than lost
+    backup::replace_with_backups(&key_bindings_path, bindings.as_str().as_bytes(), backup::BACKUP_COUNT)
+        .map_err(|err| PromoteError::Saving(key_bindings_path, err))?;
+    backup::replace_with_backups(&key_recordings_path, recordings.as_str().as_bytes(), backup::BACKUP_COUNT)
+        .map_err(|err| PromoteError::Saving(key_recordings_path, err))?;
+
+    Ok(promoted)
+}
+
+/// One of the files in which bindings are kept
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BindingsFile {
+    /// [`XDG_CONFIG_KEY_BINDINGS`]
+    KeyBindings,
+    /// [`XDG_CONFIG_KEY_RECORDINGS`]
+    KeyRecordings,
+}
+impl BindingsFile {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::KeyBindings => XDG_CONFIG_KEY_BINDINGS,
+            Self::KeyRecordings => XDG_CONFIG_KEY_RECORDINGS,
+        }
+    }
+
+    fn stub(self) -> &'static str {
+        match self {
+            Self::KeyBindings => include_str!("config_stub.ron"),
+            Self::KeyRecordings => include_str!("config_stub_record.ron"),
+        }
+    }
+
+    fn place(self) -> Result<PathBuf, LoadError> {
+        xdg::BaseDirectories::with_prefix(XDG_PREFIX).place_config_file(self.name()).map_err(LoadError::Locating)
+    }
+}
+
+/// Finds the binding in effect at the given M/G coordinates (a recording taking precedence over a static binding),
+/// along with the file that it is kept in.
+///
+/// Unless `on` is given, there must not be bindings for both the press and release of the key ([`FindError::Ambiguous`]).
+pub fn find_binding(m: u8, g: u8, on: Option<Direction>) -> Result<Option<(KeyBinding, BindingsFile)>, FindError> {
+    for file in [BindingsFile::KeyRecordings, BindingsFile::KeyBindings] {
+        let document = load_document(&file.place()?, file.stub())?;
+        let mut found: Vec<&KeyBinding> = vec![];
+        for binding in document.bindings().filter(|binding| binding.m == m && binding.g == g && on.is_none_or(|on| binding.on == on)) {
+            //Where there are duplicates, the last one wins (as it does when loading)
+            found.retain(|earlier| earlier.on != binding.on);
+            found.push(binding);
+        }
+        match found.as_slice() {
+            [] => continue,
+            [binding] => return Ok(Some(((*binding).clone(), file))),
+            _ => return Err(FindError::Ambiguous(m, g)),
+        }
+    }
+    Ok(None)
+}
+
+/// Replaces the binding with the same coordinates in the given file (or adds it, in M/G order),
+/// preserving the rest of the file and keeping the previous version as a backup.
+/// Refuses to save a binding whose macro calls are invalid ([`SaveBindingError::InvalidMacros`]).
+pub fn save_binding(binding: KeyBinding, file: BindingsFile) -> Result<PathBuf, SaveBindingError> {
+    validate_saved_calls(std::slice::from_ref(&binding))?;
+    let _lock = lock_config()?;
+    let path = file.place()?;
+    let mut document = load_document(&path, file.stub())?;
+    document.upsert_in_order(binding)
+        .map_err(|err| LoadError::unable_to_parse_or_load_config(path.clone(), err))?;
+
+    backup::replace_with_backups(&path, document.as_str().as_bytes(), backup::BACKUP_COUNT)
+        .map(|()| path.clone())
+        .map_err(|err| SaveBindingError::Saving(path, err))
+}
+
+/// Reads every binding in the given file (which is empty if it does not yet exist)
+pub fn load_bindings_file(file: BindingsFile) -> Result<Vec<KeyBinding>, LoadError> {
+    Ok(load_document(&file.place()?, file.stub())?.bindings().cloned().collect())
+}
+
+/// Applies edits to the [`XDG_CONFIG_KEY_BINDINGS`] file: each changed binding replaces the one at its coordinates (or is added, in M/G order),
+/// and the bindings at the removed coordinates are removed. The rest of the file is left as-is, comments/formatting included
+/// (as are any bindings added since the edits began, e.g. by `promote`).
+/// Refuses to save bindings whose macro calls are invalid ([`SaveBindingError::InvalidMacros`]).
+pub fn update_key_bindings(changed: &[KeyBinding], removed: &[(u8, u8, Direction)]) -> Result<PathBuf, SaveBindingError> {
+    validate_saved_calls(changed)?;
+    let _lock = lock_config()?;
+    let path = BindingsFile::KeyBindings.place()?;
+    let mut document = load_document(&path, BindingsFile::KeyBindings.stub())?;
+    update_document(&mut document, changed, removed)
+        .map_err(|err| LoadError::unable_to_parse_or_load_config(path.clone(), err))?;
+
+    backup::replace_with_backups(&path, document.as_str().as_bytes(), backup::BACKUP_COUNT)
+        .map(|()| path.clone())
+        .map_err(|err| SaveBindingError::Saving(path, err))
+}
+
+fn update_document(document: &mut BindingsDocument, changed: &[KeyBinding], removed: &[(u8, u8, Direction)]) -> Result<(), SpannedError> {
+    for &(m, g, on) in removed {
+        document.remove(m, g, on)?;
+    }
+    for binding in changed {
+        let unchanged = document.bindings().filter(|existing| existing.is_at(binding.m, binding.g, binding.on)).last() == Some(binding);
+        if !unchanged {
+            document.upsert_in_order(binding.clone())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the file into a [`BindingsDocument`], or starts from the given stub if it does not yet exist
+fn load_document(path: &Path, stub: &str) -> Result<BindingsDocument, LoadError> {
+    let text =
+        if path.try_exists().map_err(LoadError::Locating)? {
+            fs::read_to_string(path).map_err(|err| LoadError::Loading(path.into(), err))?
+        }
+        else { stub.to_owned() };
+    BindingsDocument::parse(text)
+        .map_err(|err| LoadError::unable_to_parse_or_load_config(path.into(), err))
+}
+
+/// Replaces the [`XDG_CONFIG_KEY_RECORDINGS`] file with one of its backups (`1` being the most recent).
+/// The replaced version becomes the most recent backup, so this may be undone by restoring version `1`.
+pub fn restore_recorded_macros(version: usize) -> Result<PathBuf, RestoreError> {
+    let _lock = lock_config().map_err(RestoreError::Locking)?;
+    let key_recordings_path = xdg::BaseDirectories::with_prefix(XDG_PREFIX)
+        .place_config_file(XDG_CONFIG_KEY_RECORDINGS)
+        .map_err(RestoreError::Locating)?;
+
+    backup::restore(&key_recordings_path, version, backup::BACKUP_COUNT)
+        .map(|()| key_recordings_path.clone())
+        .map_err(|err| RestoreError::Restoring(backup::backup_path(&key_recordings_path, version), err))
+}
+
+/// Lists the available backups of the [`XDG_CONFIG_KEY_RECORDINGS`] file (version and path), most recent first
+pub fn list_recorded_macro_backups() -> Result<Vec<(usize, PathBuf)>, RestoreError> {
+    xdg::BaseDirectories::with_prefix(XDG_PREFIX)
+        .place_config_file(XDG_CONFIG_KEY_RECORDINGS)
+        .and_then(|key_recordings_path| backup::list(&key_recordings_path, backup::BACKUP_COUNT))
+        .map_err(RestoreError::Locating)
+}
+
+#[derive(Debug, Display, Error)]
+pub enum LoadError {
+    #[display("Unable to locate the config file! Cause: {_0}")]
+    Locating(io::Error),
+    #[display("Unable to load the config from {}! Cause: {_1}", _0.display())]
+    Loading(PathBuf, io::Error),
+    #[display("Unable to parse the config from {}! Cause: {_1}", _0.display())]
+    Parsing(PathBuf, SpannedError),
+    #[display("Invalid macros in the config! Cause: {_0}")]
+    InvalidMacros(MacroError),
+}
+impl LoadError {
+    fn unable_to_parse_or_load_config(path: PathBuf, ron_err: SpannedError) -> Self {
+        if let SpannedError { code: ron::Error::Io(io_err), .. } = ron_err {
+            Self::Loading(path, io::Error::other(io_err))
+        } else {
+            Self::Parsing(path, ron_err)
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum SaveRecordedMacroError {
+    #[display("Unable to load the existing recordings file! Cause: {_0}")]
+    Loading(LoadError),
+    #[display("Unable to save the recorded macro to {}! Cause: {_1}", _0.display())]
+    Saving(PathBuf, io::Error),
+    #[display("Not saved, since the script's macro calls are invalid: {_0}")]
+    InvalidMacros(MacroError),
+}
+impl From<LoadError> for SaveRecordedMacroError {
+    fn from(err: LoadError) -> Self {
+        match err {
+            LoadError::InvalidMacros(err) => Self::InvalidMacros(err),
+            err => Self::Loading(err),
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum PromoteError {
+    #[display("{_0}")]
+    Loading(LoadError),
+    #[display("There are no matching recorded macros to promote")]
+    NothingToPromote,
+    #[display("Refusing to overwrite existing static binding(s): {}", _0.iter().map(|binding| format!("M{} G{} (on {:?})", binding.m, binding.g, binding.on)).collect::<Vec<_>>().join(", "))]
+    WouldOverwrite(#[error(not(source))] Vec<KeyBinding>),
+    #[display("Unable to save {}! Cause: {_1}", _0.display())]
+    Saving(PathBuf, io::Error),
+}
+impl From<LoadError> for PromoteError {
+    fn from(err: LoadError) -> Self { Self::Loading(err) }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum FindError {
+    #[display("{_0}")]
+    Loading(LoadError),
+    #[display("M{_0} G{_1} has bindings for both its press and release; please specify which")]
+    Ambiguous(#[error(not(source))] u8, #[error(not(source))] u8),
+}
+impl From<LoadError> for FindError {
+    fn from(err: LoadError) -> Self { Self::Loading(err) }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum SaveBindingError {
+    #[display("{_0}")]
+    Loading(LoadError),
+    #[display("Unable to save {}! Cause: {_1}", _0.display())]
+    Saving(PathBuf, io::Error),
+    #[display("Not saved, since the macro calls are invalid: {_0}")]
+    InvalidMacros(MacroError),
+}
+impl From<LoadError> for SaveBindingError {
+    fn from(err: LoadError) -> Self {
+        match err {
+            LoadError::InvalidMacros(err) => Self::InvalidMacros(err),
+            err => Self::Loading(err),
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum UndoError {
+    #[display("{_0}")]
+    Loading(LoadError),
+    #[display("Unable to save {}! Cause: {_1}", _0.display())]
+    Saving(PathBuf, io::Error),
+}
+impl From<LoadError> for UndoError {
+    fn from(err: LoadError) -> Self { Self::Loading(err) }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum RestoreError {
+    #[display("Unable to locate the recordings file or its backups! Cause: {_0}")]
+    Locating(io::Error),
+    #[display("Unable to restore from {}! Cause: {_1}", _0.display())]
+    Restoring(PathBuf, io::Error),
+    #[display("{_0}")]
+    Locking(LoadError),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use enigo::Direction::*;
+    use super::*;
+
+    #[test]
+    fn validates_calls_before_saving() {
+        let calling = |name: &str| [KeyBinding::new(1, 1, Press, vec![Step::Call(name.into(), Default::default())])];
+        let defined = || Ok(Macros::from([("close-tab".into(), vec![Step::Text("x".into())])]));
+
+        assert!(validate_calls(&calling("close-tab"), defined).is_ok());
+        assert!(matches!(validate_calls(&calling("nowhere"), defined), Err(LoadError::InvalidMacros(MacroError::Undefined(..)))));
+        assert!(
+            validate_calls(&[KeyBinding::new(1, 1, Press, vec![Step::Text("x".into())])], || panic!("macros are not needed")).is_ok(),
+        );
+        assert!(matches!(SaveBindingError::from(LoadError::InvalidMacros(MacroError::Cycle(vec![]))), SaveBindingError::InvalidMacros(_)));
+    }
+
+    #[test] fn tolerant_of_missing_open_bracket(){ parses_correctly(true, false, false); }
+    #[test] fn tolerant_of_missing_close_bracket(){ parses_correctly(false, true, false); }
+    #[test] fn tolerant_of_missing_brackets(){ parses_correctly(true, true, false); }
+    #[test] fn tolerant_of_missing_extensions(){ parses_correctly(false, false, true); }
+    #[test] fn parses_correctly_when_valid() { parses_correctly(false, false, false); }
+    fn parses_correctly(missing_open_bracket: bool, missing_close_bracket: bool, missing_extensions: bool) {
+        let config = Config {
+            key_bindings: vec![
+                KeyBinding::new(1, 1, Press, vec![
+                    Step::Key(enigo::Key::Control,      Press),
+                    Step::Key(enigo::Key::Unicode('-'), Click),
+                    Step::Key(enigo::Key::Control,      Release),
+                ]),
+                KeyBinding::new(1, 2, Press, vec![
+                    Step::Key(enigo::Key::Control,      Press),
+                    Step::Key(enigo::Key::Unicode('0'), Click),
+                    Step::Key(enigo::Key::Control,      Release),
+                ]),
+            ],
+            macros: Macros::default(),
+            settings: Settings::default(),
+        };
+
+        let input = format!(r"
+            {extensions}
+            {open}
+                KeyBinding(
+                    m: 1,
+                    g: 1,
+                    on: Press,
+                    script: [
+                        Key(Control, Press),
+                        Key(Unicode('-'), Click),
+                        Key(Control, Release),
+                    ],
+                ),
+                KeyBinding(
+                    m: 1,
+                    g: 2,
+                    on: Press,
+                    script: [
+                        Key(Control, Press),
+                        Key(Unicode('0'), Click),
+                        Key(Control, Release),
+                    ],
+                ),
+            {close}
+        ", open = if missing_open_bracket { "" } else { "[" }, close = if missing_close_bracket { "" } else { "]" },
+           extensions = if missing_extensions { "" } else { "#![enable(explicit_struct_names, implicit_some)]" },
+        );
+
+        let parsed = try_load_key_bindings(Path::new("n/a"), || Ok(BufReader::new(input.as_bytes())), None, false).expect("does not fail to parse");
+
+        assert_eq!(parsed, config.key_bindings);
+    }
+
+    #[test]
+    fn updates_only_the_changed_bindings() {
+        let mut document = BindingsDocument::parse(r#"[
+            // Kept
+            KeyBinding(m: 1, g: 1, on: Press, script: [Text("one")]),
+            // Removed
+            KeyBinding(m: 1, g: 2, on: Press, script: [Text("two")]),
+            KeyBinding(m: 2, g: 1, on: Press, script: [Text("old")]),
+            // Added by someone else in the meantime
+            KeyBinding(m: 3, g: 1, on: Press, script: [Text("promoted")]),
+        ]"#.into()).expect("valid");
+        let changed = vec![
+            KeyBinding::new(1, 1, Press, vec![Step::Text("one".into())]),
+            KeyBinding::new(2, 1, Press, vec![Step::Text("new".into())]),
+            KeyBinding::new(1, 3, Release, vec![]),
+        ];
+
+        update_document(&mut document, &changed, &[(1, 2, Press), (3, 2, Press)]).expect("can update");
+
+        assert!(document.as_str().contains(r#"// Kept
+            KeyBinding(m: 1, g: 1, on: Press, script: [Text("one")]),"#));
+        assert!(!document.as_str().contains("two"));
+        assert_eq!(document.bindings().cloned().collect::<Vec<_>>(), vec![
+            changed[0].clone(),
+            changed[2].clone(),
+            changed[1].clone(),
+            KeyBinding::new(3, 1, Press, vec![Step::Text("promoted".into())]),
+        ]);
+    }
+
+    #[test]
+    fn prebaked_stub_is_valid() {
+        let parsed = ron::from_str::<Vec<KeyBinding>>(include_str!("config_stub.ron"))
+            .expect("does not fail to parse");
+
+        assert_eq!(parsed, vec![]);
+    }
+}
@@ -0,0 +1,137 @@
+//! Optional daemon settings, read from the [`XDG_CONFIG_SETTINGS`](super::XDG_CONFIG_SETTINGS) file.
+//! Every field has a default, so the file (or any part of it) may be omitted.
+
+use std::{path::PathBuf, time::Duration};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// How macros are recorded with the `MR` key
+    pub recording: RecordingSettings,
+    /// The localhost port on which to serve the web UI for editing bindings (which is disabled unless set)
+    pub web_ui_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RecordingSettings {
+    /// Where the keystrokes being recorded are read from
+    pub source: RecordSource,
+    /// How the delays between recorded keystrokes are turned into [`Step::Sleep`](crate::steps::Step::Sleep) steps
+    pub timing: RecordTiming,
+    /// The keyboard layout with which recorded keystrokes are interpreted
+    pub layout: KeyboardLayout,
+    /// Whether mouse clicks, scrolling and movement are recorded too (read via evdev from `/dev/input`)
+    pub pointer: bool,
+    /// How long (in milliseconds) the G key must be held when choosing it, for the macro to run upon release rather than press
+    pub release_hold_millis: u64,
+}
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            source: RecordSource::default(),
+            timing: RecordTiming::default(),
+            layout: KeyboardLayout::default(),
+            pointer: false,
+            release_hold_millis: 600,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RecordSource {
+    /// The G11's standard keyboard interface, via hidraw (which requires its own udev rule)
+    #[default]
+    Hidraw,
+    /// Every keyboard under `/dev/input` (which requires membership of the `input` group)
+    Evdev,
+    /// A specific keyboard's device node, e.g. a link within `/dev/input/by-id`
+    EvdevDevice(PathBuf),
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub enum RecordTiming {
+    /// Keystrokes are replayed as quickly as possible
+    #[default]
+    Drop,
+    /// Keystrokes are replayed with the same delays as when they were recorded
+    AsRecorded,
+    /// Delays are multiplied by the given factor (e.g. `0.5` to replay at double speed)
+    Scaled(f32),
+    /// Delays are kept, but shortened to at most the given number of milliseconds
+    Capped(u64),
+}
+impl RecordTiming {
+    /// Translates the time observed between two recorded keystrokes into the delay to be scripted (in milliseconds, if any)
+    pub fn delay_millis(self, elapsed: Duration) -> Option<u64> {
+        let elapsed_millis = elapsed.as_secs_f64() * 1000.0;
+        match self {
+            Self::Drop => None,
+            Self::AsRecorded => Some(elapsed_millis),
+            Self::Scaled(factor) => Some(elapsed_millis * f64::from(factor.max(0.0))),
+            Self::Capped(max_millis) => Some(elapsed_millis.min(max_millis as f64)),
+        }
+        .map(|millis| millis.round() as u64)
+        .filter(|millis| *millis > 0)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum KeyboardLayout {
+    /// The standard US layout
+    #[default]
+    Us,
+    /// Any XKB layout (as would be passed to `setxkbmap`).
+    /// Blank names fall back to the system defaults (which may be given via the `XKB_DEFAULT_*` environment variables).
+    Xkb(XkbNames),
+}
+
+/// The RMLVO names that identify an XKB keymap
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct XkbNames {
+    pub rules: String,
+    pub model: String,
+    /// e.g. `"de"`
+    pub layout: String,
+    /// e.g. `"nodeadkeys"`
+    pub variant: String,
+    pub options: Option<String>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_is_default() {
+        assert_eq!(ron::from_str::<Settings>("()").expect("parses"), Settings::default());
+        assert_eq!(ron::from_str::<Settings>("Settings(recording: ())").expect("parses"), Settings::default());
+        assert_eq!(Settings::default().recording.release_hold_millis, 600);
+        assert_eq!(Settings::default().web_ui_port, None);
+    }
+
+    #[test]
+    fn xkb_layout_names_are_optional() {
+        let settings: Settings = ron::from_str(r#"(recording: (layout: Xkb((layout: "de"))))"#).expect("parses");
+
+        assert_eq!(
+            settings.recording.layout,
+            KeyboardLayout::Xkb(XkbNames { layout: "de".into(), ..XkbNames::default() }),
+        );
+    }
+
+    #[test]
+    fn timing_modes() {
+        let elapsed = Duration::from_millis(400);
+
+        assert_eq!(RecordTiming::Drop.delay_millis(elapsed), None);
+        assert_eq!(RecordTiming::AsRecorded.delay_millis(elapsed), Some(400));
+        assert_eq!(RecordTiming::Scaled(0.5).delay_millis(elapsed), Some(200));
+        assert_eq!(RecordTiming::Capped(250).delay_millis(elapsed), Some(250));
+        assert_eq!(RecordTiming::Capped(250).delay_millis(Duration::from_millis(100)), Some(100));
+        assert_eq!(RecordTiming::AsRecorded.delay_millis(Duration::from_micros(10)), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! Named variables (numbers or text), which scripts may set, increment and type (e.g. the next invoice number).
+//!
+//! Their values are kept in the [`XDG_STATE_VARIABLES`](super::XDG_STATE_VARIABLES) file,
+//! under `$XDG_STATE_HOME` (usually `~/.local/state`), so that they survive restarts of the daemon.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::PathBuf,
+};
+use derive_more::{Display, Error};
+use ron::ser::PrettyConfig;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use super::{backup, LoadError, XDG_PREFIX, XDG_STATE_VARIABLES};
+
+/// The value of a variable, written in RON as-is (e.g. `1042` or `"draft"`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Value {
+    Number(i64),
+    Text(String),
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{number}"),
+            Value::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+/// The variables, by name, along with where they are saved (if anywhere)
+#[derive(Debug, Default)]
+pub struct Variables {
+    values: BTreeMap<String, Value>,
+    /// `None` if the values are only to be kept in memory (e.g. since the file could not be loaded, and must not be overwritten)
+    path: Option<PathBuf>,
+    /// Whether the values have changed since they were loaded or last saved
+    changed: bool,
+}
+impl Variables {
+    /// Loads the [`XDG_STATE_VARIABLES`] file, there being no variables if it does not yet exist
+    pub fn load() -> Result<Self, LoadError> {
+        xdg::BaseDirectories::with_prefix(XDG_PREFIX)
+            .place_state_file(XDG_STATE_VARIABLES)
+            .map_err(LoadError::Locating)
+            .and_then(Self::load_from)
+    }
+
+    /// Loads the given file (rather than the usual one)
+    pub fn load_from(path: PathBuf) -> Result<Self, LoadError> {
+        let values = match fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).map_err(|err| LoadError::unable_to_parse_or_load_config(path.clone(), err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(LoadError::Loading(path, err)),
+        };
+        Ok(Self { values, path: Some(path), changed: false })
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Value, VariableError> {
+        self.values.get(name).ok_or_else(|| VariableError::Undefined(name.into()))
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        if self.values.get(name) != Some(&value) {
+            self.values.insert(name.into(), value);
+            self.changed = true;
+        }
+    }
+
+    /// Adds the amount to the numeric variable (which starts from 0 if it is not yet defined), returning its new value
+    pub fn increment(&mut self, name: &str, amount: i64) -> Result<i64, VariableError> {
+        let number = match self.values.get(name) {
+            Some(Value::Number(number)) => number.checked_add(amount).ok_or_else(|| VariableError::Overflow(name.into()))?,
+            Some(Value::Text(_)) => return Err(VariableError::NotANumber(name.into())),
+            None => amount,
+        };
+        self.set(name, Value::Number(number));
+        Ok(number)
+    }
+
+    /// The values to be saved, if any have changed since they were loaded (or last taken), so that they may be saved in the background
+    pub fn take_changes(&mut self) -> Option<VariablesSnapshot> {
+        let path = self.path.clone().filter(|_| self.changed)?;
+        self.changed = false;
+        Some(VariablesSnapshot { values: self.values.clone(), path })
+    }
+}
+
+/// The values of the variables at some point, to be saved to the file they were loaded from (see [`Variables::take_changes`])
+#[derive(Clone)]
+pub struct VariablesSnapshot {
+    values: BTreeMap<String, Value>,
+    path: PathBuf,
+}
+impl VariablesSnapshot {
+    /// Replaces the [`XDG_STATE_VARIABLES`] file
+    pub fn save(&self) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(&self.values, PrettyConfig::default()).map_err(io::Error::other)?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        backup::replace_with_backups(&self.path, text.as_bytes(), 0)
+    }
+}
+//Rather than every value, which would make for noisy logs
+impl fmt::Debug for VariablesSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} variables for {}", self.values.len(), self.path.display())
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum VariableError {
+    #[display("There is no variable named {_0:?}")]
+    Undefined(#[error(not(source))] String),
+    #[display("Variable {_0:?} cannot be incremented, since it is not a number")]
+    NotANumber(#[error(not(source))] String),
+    #[display("Variable {_0:?} cannot be incremented any further")]
+    Overflow(#[error(not(source))] String),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::steps::Step;
+    use super::*;
+
+    #[test]
+    fn increments_and_sets() {
+        let mut variables = Variables::default();
+
+        assert_eq!(variables.increment("invoice", 1).ok(), Some(1));
+        assert_eq!(variables.increment("invoice", 5).ok(), Some(6));
+        variables.set("invoice", Value::Number(1000));
+        assert_eq!(variables.increment("invoice", -1).ok(), Some(999));
+        variables.set("status", Value::Text("draft".into()));
+        assert!(matches!(variables.increment("status", 1), Err(VariableError::NotANumber(_))));
+        assert_eq!(variables.get("status").map(Value::to_string).ok(), Some("draft".into()));
+        assert!(matches!(variables.get("nothing"), Err(VariableError::Undefined(_))));
+        variables.set("max", Value::Number(i64::MAX));
+        assert!(matches!(variables.increment("max", 1), Err(VariableError::Overflow(_))));
+    }
+
+    #[test]
+    fn persists_changes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join(XDG_STATE_VARIABLES);
+
+        let mut variables = Variables::load_from(path.clone()).expect("loads nothing");
+        assert!(variables.take_changes().is_none(), "nothing has changed");
+
+        variables.increment("invoice", 1042).expect("increments");
+        variables.set("greeting", Value::Text("hi".into()));
+        variables.take_changes().expect("has changed").save().expect("saves");
+        assert!(variables.take_changes().is_none(), "the changes were taken");
+
+        let reloaded = Variables::load_from(path.clone()).expect("loads");
+        assert_eq!(reloaded.get("invoice").ok(), Some(&Value::Number(1042)));
+        assert_eq!(reloaded.get("greeting").ok(), Some(&Value::Text("hi".into())));
+    }
+
+    #[test]
+    fn parses_variable_steps() {
+        let script: Vec<Step> = ron::from_str(r#"[SetVariable("invoice", 1000), SetVariable("status", "draft"), IncrementVariable("invoice"), TypeVariable("invoice")]"#)
+            .expect("parses");
+
+        assert_eq!(script, vec![
+            Step::SetVariable("invoice".into(), Value::Number(1000)),
+            Step::SetVariable("status".into(), Value::Text("draft".into())),
+            Step::IncrementVariable("invoice".into(), 1),
+            Step::TypeVariable("invoice".into()),
+        ]);
+    }
+}
@@ -0,0 +1,212 @@
+//! A Unix socket through which other processes (such as the `edit` subcommand) may ask the running daemon to act,
+//! located at `$XDG_RUNTIME_DIR/g11-macro-daemon/control.sock`.
+//!
+//! Each connection carries a single command line, to which the daemon replies with a single line: `ok`, or `error: <cause>`.
+//! The commands are:
+//! - `reload`: reload the config
+//! - `promote all [--force]` or `promote <M> <G> [--force]`: promote recorded macros into the key bindings file
+//!   (see [`config::promote_recorded_macros`]), then reload the config (the arguments being as for the `promote` subcommand)
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+use derive_more::{Display, Error};
+use log::{info, warn};
+use crate::{
+    arguments,
+    config::{self, Config, KeyBinding, LoadError, PromoteError},
+};
+
+pub const XDG_RUNTIME_CONTROL_SOCKET: &str = "control.sock";
+
+/// How long a client has to send its command (or read the reply) before being disconnected
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Something the daemon has been asked to do
+#[derive(Debug)]
+pub enum Request {
+    /// Apply the freshly loaded config (in place of the current one)
+    Reload(Config),
+}
+
+/// Moves the recorded macros (at the given M/G coordinates, or all of them) into the key bindings file,
+/// overwriting any differing static bindings only if asked to
+type Promote = dyn Fn(Option<(u8, u8)>, bool) -> Result<Vec<KeyBinding>, PromoteError> + Send;
+
+/// Accepts connections on a dedicated thread, passing on each [`Request`] to be collected via [`Listener::try_request`]
+pub struct Listener(Receiver<Request>);
+impl Listener {
+    pub fn spawn() -> io::Result<Self> {
+        let socket_path = xdg::BaseDirectories::with_prefix(config::XDG_PREFIX).place_runtime_file(XDG_RUNTIME_CONTROL_SOCKET)?;
+        Self::spawn_at(&socket_path, config::ensure_and_load_config_file, Box::new(config::promote_recorded_macros))
+    }
+
+    fn spawn_at(socket_path: &Path, load_config: impl Fn() -> Result<Config, LoadError> + Send + 'static, promote: Box<Promote>) -> io::Result<Self> {
+        //(any leftover socket belongs to a daemon that is no longer running)
+        match fs::remove_file(socket_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let listener = UnixListener::bind(socket_path)?;
+        let (requests, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("control".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let _ = stream
+                        .and_then(|stream| serve(stream, &requests, &load_config, &promote))
+                        .inspect_err(|err| warn!("Failed to serve a control connection: {err}"));
+                }
+            })?;
+
+        info!("Listening for control commands on {}", socket_path.display());
+        Ok(Self(receiver))
+    }
+
+    /// Returns the next request, if any (without blocking)
+    pub fn try_request(&self) -> Option<Request> {
+        self.0.try_recv().ok()
+    }
+}
+
+fn serve(
+    stream: UnixStream,
+    requests: &Sender<Request>,
+    load_config: &impl Fn() -> Result<Config, LoadError>,
+    promote: &Promote,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut command = String::new();
+    BufReader::new(&stream).read_line(&mut command)?;
+
+    let reload = || load_config().map(|config| { let _ = requests.send(Request::Reload(config)); });
+    let outcome = match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["reload"] => reload().map_err(|err| err.to_string()),
+        ["promote", args @ ..] => arguments::parse_promotion(args).map_err(|err| err.to_string())
+            .and_then(|(coordinates, overwrite)| promote(coordinates, overwrite).map_err(|err| err.to_string()))
+            .and_then(|promoted| {
+                info!("Promoted {} recorded binding(s)", promoted.len());
+                reload().map_err(|err| err.to_string())
+            }),
+        _ => Err(format!("unrecognized command {:?}", command.trim())),
+    };
+    match outcome {
+        Ok(()) => writeln!(&stream, "ok"),
+        Err(cause) => writeln!(&stream, "error: {cause}"),
+    }
+}
+
+/// Asks the running daemon to reload its config, returning once it has done so
+pub fn request_reload() -> Result<(), ControlError> {
+    let socket_path = xdg::BaseDirectories::with_prefix(config::XDG_PREFIX)
+        .get_runtime_file(XDG_RUNTIME_CONTROL_SOCKET)
+        .map_err(ControlError::NotRunning)?;
+    send(&socket_path, "reload")
+}
+
+fn send(socket_path: &Path, command: &str) -> Result<(), ControlError> {
+    let stream = UnixStream::connect(socket_path).map_err(ControlError::NotRunning)?;
+    let exchange = || -> io::Result<String> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        writeln!(&stream, "{command}")?;
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply)?;
+        Ok(reply)
+    };
+    let reply = exchange().map_err(|err| ControlError::Communicating(socket_path.into(), err))?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        reply => Err(ControlError::Refused(reply.strip_prefix("error: ").unwrap_or(reply).into())),
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ControlError {
+    #[display("The daemon does not appear to be running. Cause: {_0}")]
+    NotRunning(io::Error),
+    #[display("Unable to communicate with the daemon via {}! Cause: {_1}", _0.display())]
+    Communicating(PathBuf, io::Error),
+    #[display("The daemon refused the request: {_0}")]
+    Refused(#[error(not(source))] String),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::*;
+
+    fn wait_for_request(listener: &Listener) -> Request {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(request) = listener.try_request() { return request; }
+            assert!(Instant::now() < deadline, "timed out waiting for request");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn reloads() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let socket_path = dir.path().join(XDG_RUNTIME_CONTROL_SOCKET);
+        let listener = Listener::spawn_at(&socket_path, || Ok(Config::default()), Box::new(|_, _| Ok(vec![]))).expect("can listen");
+
+        send(&socket_path, "reload").expect("daemon accepts");
+
+        assert!(matches!(wait_for_request(&listener), Request::Reload(config) if config == Config::default()));
+    }
+
+    #[test]
+    fn reports_failures() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let socket_path = dir.path().join(XDG_RUNTIME_CONTROL_SOCKET);
+        let listener = Listener::spawn_at(&socket_path, || Err(LoadError::Locating(io::Error::other("no home"))), Box::new(|_, _| Ok(vec![])))
+            .expect("can listen");
+
+        assert!(matches!(send(&socket_path, "reload"), Err(ControlError::Refused(cause)) if cause.contains("no home")));
+        assert!(matches!(send(&socket_path, "explode"), Err(ControlError::Refused(_))));
+        assert!(listener.try_request().is_none());
+    }
+
+    #[test]
+    fn promotes_then_reloads() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+        let socket_path = dir.path().join(XDG_RUNTIME_CONTROL_SOCKET);
+        let (promotions, promoted) = mpsc::channel();
+        let promote = move |coordinates, overwrite| {
+            let _ = promotions.send((coordinates, overwrite));
+            match coordinates {
+                Some((3, _)) => Err(PromoteError::NothingToPromote),
+                _ => Ok(vec![]),
+            }
+        };
+        let listener = Listener::spawn_at(&socket_path, || Ok(Config::default()), Box::new(promote)).expect("can listen");
+
+        send(&socket_path, "promote all").expect("daemon accepts");
+        assert!(matches!(wait_for_request(&listener), Request::Reload(_)));
+        send(&socket_path, "promote m1 g13 --force").expect("daemon accepts");
+        assert!(matches!(wait_for_request(&listener), Request::Reload(_)));
+        assert_eq!(promoted.try_iter().collect::<Vec<_>>(), [(None, false), (Some((1, 13)), true)]);
+
+        assert!(matches!(send(&socket_path, "promote 3 1"), Err(ControlError::Refused(cause)) if cause.contains("no matching")));
+        assert!(matches!(send(&socket_path, "promote 1"), Err(ControlError::Refused(_))));
+        assert!(matches!(send(&socket_path, "promote all please"), Err(ControlError::Refused(_))));
+        assert!(matches!(send(&socket_path, "promote M4 G1"), Err(ControlError::Refused(cause)) if cause.contains("M1 to M3")));
+        assert!(listener.try_request().is_none());
+    }
+
+    #[test]
+    fn no_daemon() {
+        let dir = tempfile::tempdir().expect("can create temp dir");
+
+        assert!(matches!(send(&dir.path().join(XDG_RUNTIME_CONTROL_SOCKET), "reload"), Err(ControlError::NotRunning(_))));
+    }
+}
@@ -0,0 +1,125 @@
+//! Editing of a binding's script in the user's own text editor (`$VISUAL`, `$EDITOR`, or else `vi`)
+
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process::Command,
+};
+use ron::{error::SpannedError, extensions::Extensions, ser::PrettyConfig};
+use crate::{config::KeyBinding, steps::Step};
+
+/// Opens the binding's script (as RON) in the editor, until it is saved as a valid list of [`Step`]s
+/// that also passes the given check (e.g. that the macros it calls exist), or the user gives up on an invalid one.
+///
+/// Returns `None` if the script was left unchanged, or the user gave up.
+pub fn edit_script<E: fmt::Display>(binding: &KeyBinding, check: impl Fn(&KeyBinding) -> Result<(), E>) -> io::Result<Option<Vec<Step>>> {
+    //(created exclusively, with a random name, lest another user have it written through a file or symlink of their own; removed on drop)
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("g11-macro-daemon-M{}-G{}-", binding.m, binding.g))
+        .suffix(".ron")
+        .tempfile()?;
+    file.write_all(render_script(binding).as_bytes())?;
+    file.flush()?;
+    let path = file.path();
+
+    let edited = loop {
+        run_editor(path)?;
+        match accept(&fs::read_to_string(path)?, binding, &check) {
+            Ok(edited) => break edited,
+            Err(err) => {
+                eprintln!("The script of {} is invalid: {err}", binding.describe());
+                if !confirm("Edit it again?")? {
+                    break None;
+                }
+            }
+        }
+    };
+    Ok(edited)
+}
+
+/// The edited script, if it is valid (`None` if it is unchanged)
+fn accept<E: fmt::Display>(text: &str, binding: &KeyBinding, check: impl Fn(&KeyBinding) -> Result<(), E>) -> Result<Option<Vec<Step>>, String> {
+    let script = parse_script(text).map_err(|err| err.to_string())?;
+    if script == binding.script {
+        return Ok(None);
+    }
+    let edited = KeyBinding { script, ..binding.clone() };
+    check(&edited).map_err(|err| err.to_string())?;
+    Ok(Some(edited.script))
+}
+
+fn run_editor(path: &Path) -> io::Result<()> {
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| "vi".into());
+    //(via the shell, since the editor may come with its own arguments, e.g. `code --wait`)
+    let status = Command::new("sh").arg("-c").arg(format!("{editor} \"$1\"")).arg("sh").arg(path).status()?;
+    if status.success() { Ok(()) } else { Err(io::Error::other(format!("{editor} exited with {status}"))) }
+}
+
+fn confirm(question: &str) -> io::Result<bool> {
+    print!("{question} [Y/n] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(!answer.trim().eq_ignore_ascii_case("n"))
+}
+
+fn render_script(binding: &KeyBinding) -> String {
+    let script = ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME)
+        .to_string_pretty(&binding.script, PrettyConfig::default())
+        .expect("Steps are always serializable");
+    let description = binding.description.as_ref().map_or(String::new(), |description| format!("// {description}\n"));
+    format!(
+        "// The script of {}; save and quit to apply your changes\n\
+         // (see CONFIGURATION.md for the available steps)\n\
+         {description}{script}\n",
+        binding.describe(),
+    )
+}
+
+fn parse_script(text: &str) -> Result<Vec<Step>, SpannedError> {
+    ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME)
+        .from_str(text)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::{Direction::*, Key};
+    use super::*;
+
+    #[test]
+    fn rendered_script_parses() {
+        let binding = KeyBinding::new(
+            2,
+            13,
+            Release,
+            vec![Step::Key(Key::Control, Press), Step::Key(Key::Unicode('w'), Click), Step::Key(Key::Control, Release), Step::Text("hi".into())],
+        );
+
+        assert_eq!(parse_script(&render_script(&binding)).expect("valid"), binding.script);
+    }
+
+    #[test]
+    fn rejects_invalid_steps() {
+        assert!(parse_script("[Key(Unicode('w'), Click), Teleport(1, 2)]").is_err());
+        assert!(parse_script("[Key(Unicode('w'))]").is_err());
+        assert_eq!(parse_script("[]").expect("valid"), vec![]);
+    }
+
+    #[test]
+    fn checks_changed_scripts() {
+        let binding = KeyBinding::new(1, 2, Press, vec![Step::Text("hi".into())]);
+        let no_calls = |edited: &KeyBinding| match edited.script.iter().any(|step| matches!(step, Step::Call(..))) {
+            true => Err("There is no such macro"),
+            false => Ok(()),
+        };
+
+        assert_eq!(accept(r#"[Text("hi")]"#, &binding, no_calls), Ok(None));
+        assert_eq!(accept(r#"[Text("bye")]"#, &binding, no_calls), Ok(Some(vec![Step::Text("bye".into())])));
+        assert_eq!(accept(r#"[Call("bye")]"#, &binding, no_calls), Err("There is no such macro".into()));
+        assert!(accept(r#"[Teleport(1, 2)]"#, &binding, no_calls).is_err());
+    }
+}
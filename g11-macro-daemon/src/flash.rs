@@ -0,0 +1,60 @@
+//! Non-blocking LED flash sequences, used to give feedback without pausing the event loop
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use g11_macro_keys::{Key, State};
+
+/// A number of blinks of a key's LED at a fixed rate
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub blinks: u8,
+    /// Duration of each on/off phase
+    pub phase: Duration,
+}
+impl Pattern {
+    /// A single, brief blink: "all good"
+    pub const ACKNOWLEDGE: Self = Self { blinks: 1, phase: Duration::from_millis(150) };
+    /// A rapid burst of blinks: "something went wrong"
+    pub const FAILURE: Self = Self { blinks: 5, phase: Duration::from_millis(60) };
+    /// A few slow blinks: "abandoned, nothing changed"
+    pub const CANCELLED: Self = Self { blinks: 3, phase: Duration::from_millis(300) };
+}
+
+/// Tracks an in-progress flash, which must be advanced by calling [`Flasher::poll`] regularly
+#[derive(Debug, Default)]
+pub struct Flasher {
+    key: Option<Key>,
+    /// When each toggle of the LED is due (alternating between lit and unlit, starting with lit)
+    toggles: VecDeque<Instant>,
+}
+impl Flasher {
+    /// Begins flashing the given key's LED (replacing any flash already in progress).
+    /// The LED is left unlit once the flash completes.
+    pub fn start(&mut self, key: Key, pattern: Pattern) {
+        let now = Instant::now();
+        self.key = Some(key);
+        self.toggles = (0..u32::from(pattern.blinks) * 2)
+            .map(|phase| now + pattern.phase * phase)
+            .collect();
+    }
+
+    pub fn is_active(&self) -> bool { !self.toggles.is_empty() }
+
+    /// Applies any toggles that have come due, returning the HID Feature Report that must be sent (if any)
+    #[must_use]
+    pub fn poll(&mut self, state: &mut State) -> Option<[u8; 4]> {
+        let key = self.key?;
+        let now = Instant::now();
+        let mut lit = None;
+        while self.toggles.front().is_some_and(|due| *due <= now) {
+            self.toggles.pop_front();
+            lit = Some(self.toggles.len() % 2 == 1);
+        }
+        match lit? {
+            true => state.light_led(key),
+            false => state.extinguish_led(key),
+        }
+    }
+}
@@ -2,20 +2,45 @@ mod config;
 mod bindings;
 mod record;
 mod steps;
+mod schema;
+mod persistence;
+mod flash;
+mod cli;
+mod control;
+mod edit;
+mod tui;
+mod web;
+mod cheatsheet;
+mod render;
+mod arguments;
 
 use enigo::{Enigo, Settings};
 use hidapi::HidApi;
-use log::error;
+use log::{error, info, warn};
 use g11_macro_keys::{usb_id, Action, Event};
 
+/// How often the daemon checks for control commands (and web UI edits), while otherwise idle
+const CONTROL_POLL_MILLIS: i32 = 250;
+
 fn main() {
     env_logger::init();
 
-    let config::Config { key_bindings } = config::ensure_and_load_config_file().expect("Unable to load config");
-    let mut binding_banks = bindings::BindingBanks::from(key_bindings);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] => run_daemon(),
+        subcommand => cli::run(subcommand),
+    }
+}
+
+fn run_daemon() {
+    let config::Config { key_bindings, macros, mut settings } = config::ensure_and_load_config_file().expect("Unable to load config");
+    let mut binding_banks = bindings::BindingBanks::new(key_bindings, macros);
 
     let api = HidApi::new().expect("Unable to acquire HID API");
     let mut enigo = Enigo::new(&Settings::default()).expect("Unable to acquire Enigo API");
+    let mut variables = config::Variables::load()
+        .inspect_err(|err| warn!("Unable to load the variables (so changes to them will not be saved): {err}"))
+        .unwrap_or_default();
 
     let hid = api.open(usb_id::VENDOR_LOGITECH, usb_id::PRODUCT_G11_MACRO).expect("Unable to open device");
     let mut usb_buf = [0_u8; 9];
@@ -25,8 +50,66 @@ fn main() {
     let _ = state.set_exact_lit_leds(&[g11_macro_keys::Key::M(1)])
         .and_then(|usb_report| hid.send_feature_report(&usb_report).ok());
 
+    let mut persistence = persistence::Worker::spawn();
+    let mut flasher = flash::Flasher::default();
+    let control = control::Listener::spawn()
+        .inspect_err(|err| warn!("Unable to listen for control commands (so the config cannot be reloaded without a restart): {err}"))
+        .ok();
+    let web_ui = settings.web_ui_port.and_then(|port| web::WebUi::spawn(port)
+        .inspect_err(|err| warn!("Unable to serve the web UI on port {port}: {err}"))
+        .ok());
+
     loop {
-        assert_eq!(hid.read(&mut usb_buf).expect("could not read from device"), 9);
+        match persistence.try_outcome() {
+            Some(persistence::Outcome { result: Ok(persistence::Completed::Saved), .. }) => flasher.start(g11_macro_keys::Key::MR, flash::Pattern::ACKNOWLEDGE),
+            Some(persistence::Outcome { result: Ok(persistence::Completed::Undone(Some(undone))), .. }) => {
+                info!("Undid the recording at M{} G{} (on {:?})", undone.m, undone.g, undone.on);
+                match undone.restored {
+                    Some(binding) => binding_banks.replace(binding),
+                    None => binding_banks.remove(undone.m, undone.g, undone.on),
+                }
+                flasher.start(g11_macro_keys::Key::MR, flash::Pattern::ACKNOWLEDGE);
+            }
+            Some(persistence::Outcome { result: Ok(persistence::Completed::Undone(None)), .. }) => {
+                info!("There are no recordings to undo");
+                flasher.start(g11_macro_keys::Key::MR, flash::Pattern::CANCELLED);
+            }
+            Some(persistence::Outcome { result: Ok(persistence::Completed::VariablesSaved | persistence::Completed::Superseded), .. }) => {}
+            //Unrelated to recording, so not shown on the 'MR' key
+            Some(persistence::Outcome { job: persistence::Job::SaveVariables(_), result: Err(err) }) => error!("{err}"),
+            Some(persistence::Outcome { job, result: Err(err) }) => {
+                error!("Unable to perform {job:?}! Cause: {err:#?}");
+                flasher.start(g11_macro_keys::Key::MR, flash::Pattern::FAILURE);
+            }
+            None => {}
+        }
+        match control.as_ref().and_then(control::Listener::try_request) {
+            Some(control::Request::Reload(config::Config { key_bindings, macros, settings: new_settings })) => {
+                let active_bank = binding_banks.active_bank();
+                binding_banks = bindings::BindingBanks::new(key_bindings, macros);
+                binding_banks.activate_bank(active_bank);
+                settings = new_settings;
+                info!("Reloaded the config");
+            }
+            None => {}
+        }
+        while let Some(binding) = web_ui.as_ref().and_then(web::WebUi::try_saved) {
+            binding_banks.replace(binding);
+        }
+        if let Some(usb_report) = flasher.poll(&mut state) {
+            let _ = hid.send_feature_report(&usb_report)
+                .inspect_err(|err| error!("Unable to update LEDs! Cause: {err:#?}"));
+        }
+
+        //Only wake up periodically while there is background work to keep an eye on (or control commands/web UI edits to listen for):
+        let timeout_millis =
+            if flasher.is_active() || persistence.is_busy() { 20 }
+            else if control.is_some() || web_ui.is_some() { CONTROL_POLL_MILLIS }
+            else { -1 };
+        match hid.read_timeout(&mut usb_buf, timeout_millis).expect("could not read from device") {
+            0 => continue,
+            bytes_read => assert_eq!(bytes_read, 9),
+        }
         match state.try_consume_event(&usb_buf) {
             Ok(Event { action: Action::Pressed, key: key@g11_macro_keys::Key::M(m_key) }) => {
                 binding_banks.activate_bank(m_key);
@@ -36,16 +119,28 @@ fn main() {
                 }
             }
             Ok(Event { action: Action::Released, key: g11_macro_keys::Key::MR }) =>
-                if let Some(new_binding) = record::run_event_loop(&api, &hid, &mut state, binding_banks.active_bank()) {
-                    binding_banks.replace(new_binding.clone());
-                    let _ = config::save_recorded_macro(new_binding) //TODO Really ought to do these disk operations in a separate thread.
-                        .inspect_err(|err| error!("Unable to save recorded macro! Cause: {err:#?}"));
+                match record::run_event_loop(&api, &hid, &mut state, binding_banks.active_bank(), &settings.recording) {
+                    record::Outcome::Recorded(new_binding) => {
+                        let new_binding = binding_banks.inherit_metadata(new_binding);
+                        binding_banks.replace(new_binding.clone());
+                        persistence.submit(persistence::Job::SaveRecordedMacro(new_binding));
+                    }
+                    record::Outcome::Cancelled => {
+                        info!("Macro recording cancelled; the existing binding (if any) is unchanged");
+                        flasher.start(g11_macro_keys::Key::MR, flash::Pattern::CANCELLED);
+                    }
+                    record::Outcome::Undo => persistence.submit(persistence::Job::UndoRecordedMacro),
+                    record::Outcome::Nothing => {}
                 },
             Ok(event) =>
-                if let Some(script) = binding_banks.script_for(event) {
-                    for step in script {
-                        let _ = step.execute(&mut enigo)
-                            .inspect_err(|err| error!("Unable to execute {step:?}! Cause: {err:#?}"));
+                if let Some(bound) = binding_banks.binding_for(event).filter(|bound| !bound.script.is_empty()) {
+                    info!("Running {}", bound.description);
+                    for step in &bound.script {
+                        let _ = step.execute(&mut enigo, &mut variables)
+                            .inspect_err(|err| error!("Unable to execute {step:?} of {}! Cause: {err:#?}", bound.description));
+                    }
+                    if let Some(snapshot) = variables.take_changes() {
+                        persistence.submit(persistence::Job::SaveVariables(snapshot));
                     }
                 },
             Err(err) =>
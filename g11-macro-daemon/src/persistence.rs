@@ -0,0 +1,241 @@
+//! Background thread for disk operations, so that a slow filesystem never delays the handling of key events
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::Duration,
+};
+use derive_more::{Display, Error};
+use log::{info, warn};
+use crate::config::{self, KeyBinding, SaveRecordedMacroError, UndoError, Undone, VariablesSnapshot};
+
+/// How long to wait before each retry of a failed job (the number of entries being the number of retries)
+const RETRY_DELAYS: [Duration; 3] = [Duration::from_secs(1), Duration::from_secs(5), Duration::from_secs(30)];
+
+/// A disk operation to be performed in the background
+#[derive(Debug, Clone)]
+pub enum Job {
+    /// See [`config::save_recorded_macro`]
+    SaveRecordedMacro(KeyBinding),
+    /// See [`config::undo_recorded_macro`] (for the most recent recording)
+    UndoRecordedMacro,
+    /// See [`VariablesSnapshot::save`] (skipped if newer values are queued to be saved after it)
+    SaveVariables(VariablesSnapshot),
+}
+impl Job {
+    /// Whether performing the job would be pointless, given the jobs queued after it
+    fn is_superseded_by(&self, queued: &VecDeque<Job>) -> bool {
+        matches!(self, Job::SaveVariables(_)) && queued.iter().any(|later| matches!(later, Job::SaveVariables(_)))
+    }
+}
+
+/// The final result of a [`Job`] (after any retries)
+#[derive(Debug)]
+pub struct Outcome {
+    pub job: Job,
+    pub result: Result<Completed, JobError>,
+}
+
+/// What a successful [`Job`] did
+#[derive(Debug)]
+pub enum Completed {
+    Saved,
+    /// `None` if there was nothing to undo
+    Undone(Option<Undone>),
+    VariablesSaved,
+    /// Skipped, since a later job would undo its effect (see [`Job::SaveVariables`])
+    Superseded,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum JobError {
+    #[display("{_0}")]
+    Saving(SaveRecordedMacroError),
+    #[display("{_0}")]
+    Undoing(UndoError),
+    #[display("Unable to save the variables! Cause: {_0}")]
+    SavingVariables(io::Error),
+}
+
+/// Performs [`Job`]s one at a time, in the order they were submitted, on a dedicated thread
+pub struct Worker {
+    jobs: Sender<Job>,
+    outcomes: Receiver<Outcome>,
+    /// Jobs submitted whose [`Outcome`] has not yet been collected
+    pending: usize,
+}
+impl Worker {
+    pub fn spawn() -> Self {
+        Self::spawn_with(&RETRY_DELAYS, |job| match job {
+            Job::SaveRecordedMacro(binding) => config::save_recorded_macro(binding.clone())
+                .map(|()| Completed::Saved).map_err(JobError::Saving),
+            Job::UndoRecordedMacro => config::undo_recorded_macro(None)
+                .map(Completed::Undone).map_err(JobError::Undoing),
+            Job::SaveVariables(snapshot) => snapshot.save()
+                .map(|()| Completed::VariablesSaved).map_err(JobError::SavingVariables),
+        })
+    }
+
+    fn spawn_with(
+        retry_delays: &'static [Duration],
+        mut perform: impl FnMut(&Job) -> Result<Completed, JobError> + Send + 'static,
+    ) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (outcome_sender, outcomes) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("persistence".into())
+            .spawn(move || {
+                let mut queued = VecDeque::new();
+                while let Some(job) = queued.pop_front().or_else(|| job_receiver.recv().ok()) {
+                    queued.extend(job_receiver.try_iter());
+                    if job.is_superseded_by(&queued) {
+                        if outcome_sender.send(Outcome { job, result: Ok(Completed::Superseded) }).is_err() {
+                            break; //Nobody is listening anymore
+                        }
+                        continue;
+                    }
+                    let mut result = perform(&job);
+                    for delay in retry_delays {
+                        let Err(err) = &result else { break };
+                        warn!("Failed to perform {job:?}; will retry in {delay:?}. Cause: {err}");
+                        thread::sleep(*delay);
+                        result = perform(&job);
+                    }
+                    if result.is_ok() {
+                        info!("Completed {job:?}");
+                    }
+                    if outcome_sender.send(Outcome { job, result }).is_err() {
+                        break; //Nobody is listening anymore
+                    }
+                }
+            })
+            .expect("Unable to spawn persistence thread");
+
+        Self { jobs, outcomes, pending: 0 }
+    }
+
+    /// Queues the job to be performed in the background; collect its result via [`Self::try_outcome`]
+    pub fn submit(&mut self, job: Job) {
+        match self.jobs.send(job) {
+            Ok(()) => self.pending += 1,
+            Err(mpsc::SendError(job)) => warn!("Persistence thread has stopped; dropping {job:?}"),
+        }
+    }
+
+    /// Returns the outcome of the next completed job, if any (without blocking)
+    pub fn try_outcome(&mut self) -> Option<Outcome> {
+        match self.outcomes.try_recv() {
+            Ok(outcome) => {
+                self.pending -= 1;
+                Some(outcome)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.pending = 0;
+                None
+            }
+        }
+    }
+
+    /// Whether any submitted jobs have yet to report an outcome
+    pub fn is_busy(&self) -> bool { self.pending > 0 }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Instant};
+    use enigo::Direction::Press;
+    use super::*;
+
+    fn job(g: u8) -> Job {
+        Job::SaveRecordedMacro(KeyBinding::new(1, g, Press, vec![]))
+    }
+
+    fn failure() -> JobError {
+        JobError::Saving(SaveRecordedMacroError::Saving("n/a".into(), io::Error::other("disk full")))
+    }
+
+    fn wait_for_outcome(worker: &mut Worker) -> Outcome {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(outcome) = worker.try_outcome() { return outcome; }
+            assert!(Instant::now() < deadline, "timed out waiting for outcome");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn performs_jobs_in_order() {
+        let (performed_sender, performed) = mpsc::channel();
+        let mut worker = Worker::spawn_with(&[], move |job| {
+            let Job::SaveRecordedMacro(binding) = job else { panic!("only saves are submitted") };
+            performed_sender.send(binding.g).expect("test is listening");
+            Ok(Completed::Saved)
+        });
+
+        for g in 1..=3 {
+            worker.submit(job(g));
+        }
+        for _ in 1..=3 {
+            assert!(wait_for_outcome(&mut worker).result.is_ok());
+        }
+
+        assert!(!worker.is_busy());
+        assert_eq!(performed.try_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn retries_failures() {
+        let mut attempts = 0;
+        let mut worker = Worker::spawn_with(&[Duration::ZERO; 2], move |_| {
+            attempts += 1;
+            if attempts < 3 { Err(failure()) } else { Ok(Completed::Saved) }
+        });
+
+        worker.submit(job(1));
+
+        assert!(wait_for_outcome(&mut worker).result.is_ok());
+    }
+
+    #[test]
+    fn reports_failure_once_retries_are_exhausted() {
+        let mut worker = Worker::spawn_with(&[Duration::ZERO; 2], |_| Err(failure()));
+
+        worker.submit(job(1));
+
+        let outcome = wait_for_outcome(&mut worker);
+        assert!(matches!(outcome.job, Job::SaveRecordedMacro(KeyBinding { g: 1, .. })));
+        assert!(outcome.result.is_err());
+        assert!(!worker.is_busy());
+    }
+
+    #[test]
+    fn saves_only_the_latest_variables() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let mut variables = config::Variables::load_from(dir.path().join(config::XDG_STATE_VARIABLES)).expect("loads nothing");
+        let (release_sender, release) = mpsc::channel::<()>();
+        let mut worker = Worker::spawn_with(&[], move |job| match job {
+            Job::SaveRecordedMacro(_) => {
+                release.recv().expect("test releases the worker");
+                Ok(Completed::Saved)
+            }
+            _ => Ok(Completed::VariablesSaved),
+        });
+
+        //While the worker is busy, the variables change three times
+        worker.submit(job(1));
+        for number in 1..=3 {
+            variables.increment("invoice", number).expect("increments");
+            worker.submit(Job::SaveVariables(variables.take_changes().expect("changed")));
+        }
+        release_sender.send(()).expect("worker is listening");
+
+        let completed: Vec<_> = (0..4).map(|_| wait_for_outcome(&mut worker).result.expect("succeeds")).collect();
+        assert!(matches!(completed[..], [Completed::Saved, Completed::Superseded, Completed::Superseded, Completed::VariablesSaved]));
+        assert!(!worker.is_busy());
+    }
+}
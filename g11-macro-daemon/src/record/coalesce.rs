@@ -0,0 +1,207 @@
+//! Post-processing of a recorded script, so that typed text reads as text
+
+use enigo::{Direction, Key};
+use crate::steps::{placeholders, Step};
+use super::{layout::Layout, mapping::keysym};
+
+/// Folds runs of printable keystrokes (including shifted ones, as typed on the given [`Layout`]) into [`Step::Text`]
+/// (with any braces escaped, lest they be taken for [placeholders]).
+///
+/// Keystrokes are left alone where they could not have produced text:
+/// non-printable keys, or any key struck while a shortcut modifier (Control/Alt/Meta) or AltGr is held.
+/// Shift is only kept where it applies to such a keystroke.
+pub fn coalesce_text(script: Vec<Step>, layout: &Layout) -> Vec<Step> {
+    let mut coalesced = Vec::with_capacity(script.len());
+    let mut text = String::new();
+    //Shortcut modifiers currently held
+    let mut modifiers: Vec<Key> = vec![];
+    //The Shift key currently held, and whether its press has been emitted yet (it is withheld while only text is typed)
+    let mut shift: Option<(Key, bool)> = None;
+    //Printable keys that were typed on press, whose release must therefore be dropped
+    let mut typed: Vec<Key> = vec![];
+
+    for step in script {
+        match &step {
+            Step::Key(key, Direction::Press) if is_shift(*key) && modifiers.is_empty() && shift.is_none() => {
+                shift = Some((*key, false));
+                continue;
+            }
+            Step::Key(key, Direction::Release) if shift.is_some_and(|(shift, emitted)| shift == *key && !emitted) => {
+                shift = None;
+                continue;
+            }
+            Step::Key(key, Direction::Release) if typed.contains(key) => {
+                typed.retain(|typed| typed != key);
+                continue;
+            }
+            //(once Shift has been emitted, it is being replayed as held, so subsequent keys must be too)
+            Step::Key(key, direction @ (Direction::Click | Direction::Press))
+              if modifiers.is_empty() && shift.is_none_or(|(_, emitted)| !emitted) => {
+                if let Some(c) = printable(layout, *key, shift.is_some()) {
+                    text.push(c);
+                    if *direction == Direction::Press {
+                        typed.push(*key);
+                    }
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        //Not text, so anything pending must be emitted first:
+        if !text.is_empty() {
+            coalesced.push(Step::Text(placeholders::escape(&std::mem::take(&mut text))));
+        }
+        if let Some((shift_key, emitted @ false)) = &mut shift {
+            coalesced.push(Step::Key(*shift_key, Direction::Press));
+            *emitted = true;
+        }
+        match step {
+            Step::Key(key, Direction::Press) if is_shortcut_modifier(key) => modifiers.push(key),
+            Step::Key(key, Direction::Release) if is_shortcut_modifier(key) => modifiers.retain(|modifier| *modifier != key),
+            Step::Key(key, Direction::Release) if shift.is_some_and(|(shift, _)| shift == key) => shift = None,
+            _ => {}
+        }
+        coalesced.push(step);
+    }
+
+    if !text.is_empty() {
+        coalesced.push(Step::Text(placeholders::escape(&text)));
+    }
+    coalesced
+}
+
+fn is_shift(key: Key) -> bool {
+    matches!(key, Key::Shift | Key::LShift | Key::RShift)
+}
+
+#[allow(deprecated)] //(the deprecated aliases of Meta may still appear in a script)
+fn is_shortcut_modifier(key: Key) -> bool {
+    matches!(key,
+        Key::Control | Key::LControl | Key::RControl |
+        Key::Alt | Key::Option |
+        Key::Meta | Key::Super | Key::Windows | Key::Command |
+        //(AltGr also blocks folding, since the characters it produces depend upon the layout)
+        Key::Other(keysym::SUPER_R | keysym::ISO_LEVEL3_SHIFT)
+    )
+}
+
+/// The character typed by the given key
+fn printable(layout: &Layout, key: Key, shifted: bool) -> Option<char> {
+    match key {
+        Key::Space => Some(' '),
+        Key::Unicode(c) if !shifted => Some(c),
+        Key::Unicode(c) => layout.shifted(c),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Direction::*;
+    use crate::config::{KeyboardLayout, XkbNames};
+    use super::*;
+
+    fn key(key: Key, direction: Direction) -> Step { Step::Key(key, direction) }
+    fn click(c: char) -> Step { Step::Key(Key::Unicode(c), Click) }
+    fn coalesce(script: Vec<Step>) -> Vec<Step> { coalesce_text(script, &Layout::Us) }
+
+    #[test]
+    fn folds_clicks_into_text() {
+        assert_eq!(
+            coalesce(vec![click('h'), click('i'), key(Key::Space, Click), click('1')]),
+            vec![Step::Text("hi 1".into())],
+        );
+    }
+
+    #[test]
+    fn folds_shifted_clicks_into_text() {
+        assert_eq!(
+            coalesce(vec![
+                key(Key::LShift, Press), click('h'), key(Key::LShift, Release),
+                click('i'),
+                key(Key::RShift, Press), click('1'), click('\''), key(Key::RShift, Release),
+            ]),
+            vec![Step::Text("Hi!\"".into())],
+        );
+    }
+
+    #[test]
+    fn escapes_braces_so_they_are_typed_as_is() {
+        let script = coalesce(vec![
+            key(Key::LShift, Press), click('['), click('d'), click(']'), key(Key::LShift, Release),
+            key(Key::Return, Click),
+            click('}'),
+        ]);
+
+        assert_eq!(script, vec![Step::Text("{{D}}".into()), key(Key::Return, Click), Step::Text("}}".into())]);
+        assert_eq!(placeholders::expand("{{D}}", &mut Default::default()), "{D}");
+    }
+
+    #[test]
+    fn folds_shifted_clicks_according_to_layout() {
+        let layout = Layout::load(&KeyboardLayout::Xkb(XkbNames { layout: "de".into(), ..XkbNames::default() }))
+            .expect("keymap compiles");
+
+        assert_eq!(
+            coalesce_text(vec![key(Key::LShift, Press), click('7'), click('ä'), key(Key::LShift, Release)], &layout),
+            vec![Step::Text("/Ä".into())],
+        );
+    }
+
+    #[test]
+    fn folds_overlapping_presses_into_text() {
+        assert_eq!(
+            coalesce(vec![
+                key(Key::Unicode('a'), Press), key(Key::Unicode('b'), Press),
+                key(Key::Unicode('a'), Release), key(Key::Unicode('b'), Release),
+            ]),
+            vec![Step::Text("ab".into())],
+        );
+    }
+
+    #[test]
+    fn keeps_shortcuts_as_keys() {
+        let script = vec![
+            key(Key::LControl, Press), click('w'), key(Key::LControl, Release),
+        ];
+        assert_eq!(coalesce(script.clone()), script);
+    }
+
+    #[test]
+    fn keeps_altgr_and_right_meta_combinations_as_keys() {
+        for modifier in [keysym::ISO_LEVEL3_SHIFT, keysym::SUPER_R] {
+            let script = vec![
+                key(Key::Other(modifier), Press), click('e'), key(Key::Other(modifier), Release),
+            ];
+            assert_eq!(coalesce(script.clone()), script);
+        }
+    }
+
+    #[test]
+    fn keeps_shift_for_non_printable_keys() {
+        assert_eq!(
+            coalesce(vec![
+                key(Key::LShift, Press), click('a'), key(Key::Tab, Click), click('b'), key(Key::LShift, Release),
+                key(Key::Return, Click),
+            ]),
+            vec![
+                Step::Text("A".into()),
+                key(Key::LShift, Press), key(Key::Tab, Click), click('b'), key(Key::LShift, Release),
+                key(Key::Return, Click),
+            ],
+        );
+    }
+
+    #[test]
+    fn other_steps_break_runs_of_text() {
+        assert_eq!(
+            coalesce(vec![click('a'), Step::Sleep(100), click('b'), key(Key::Backspace, Click), click('c')]),
+            vec![
+                Step::Text("a".into()), Step::Sleep(100), Step::Text("b".into()),
+                key(Key::Backspace, Click), Step::Text("c".into()),
+            ],
+        );
+    }
+}
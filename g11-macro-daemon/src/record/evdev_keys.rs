@@ -0,0 +1,123 @@
+//! Capture of keystrokes via evdev from `/dev/input/event*`
+//! (an alternative to [`super::standard_keys`], which works with any keyboard)
+
+use std::{io, path::Path};
+use enigo::Direction;
+use evdev::{Device, EventSummary, InputEvent, KeyCode};
+use keycode::{KeyMap, KeyMapping};
+use log::{info, warn};
+use g11_macro_keys::usb_id;
+use crate::steps::Step;
+use super::{layout::Layout, TimedStep};
+
+/// The keyboard(s) being recorded from, whose keys are interpreted according to the given [`Layout`]
+pub struct Keyboards {
+    devices: Vec<Device>,
+    layout: Layout,
+}
+impl Keyboards {
+    /// Opens every input device that has letter keys
+    /// (those that cannot be opened, e.g. due to permissions, are skipped).
+    pub fn open_all(layout: Layout) -> Self {
+        let devices: Vec<Device> = evdev::enumerate()
+            .map(|(_, device)| device)
+            .filter(is_keyboard)
+            .filter(|device| device.set_nonblocking(true)
+                .inspect_err(|err| warn!("Not recording from {:?}, which cannot be read without blocking: {err}", device.name()))
+                .is_ok())
+            .collect();
+
+        match devices.as_slice() {
+            [] => warn!("Unable to record keystrokes: no keyboard could be opened under /dev/input (is this user in the `input` group?)"),
+            devices => info!("Recording keystrokes from {:?}", devices.iter().map(Device::name).collect::<Vec<_>>()),
+        }
+        Self { devices, layout }
+    }
+
+    /// Opens the given device node (e.g. one of the links in `/dev/input/by-id`)
+    pub fn open(path: &Path, layout: Layout) -> io::Result<Self> {
+        let device = Device::open(path)?;
+        device.set_nonblocking(true)?;
+        info!("Recording keystrokes from {:?}", device.name());
+        Ok(Self { devices: vec![device], layout })
+    }
+
+    pub fn layout(&self) -> &Layout { &self.layout }
+
+    /// Returns the steps for every keystroke since the last poll (without blocking), each stamped with when it occurred
+    pub fn poll(&mut self) -> Vec<TimedStep> {
+        let mut steps = vec![];
+        for device in &mut self.devices {
+            let fetched = device.fetch_events().map(|events| events.filter_map(|event| Some((step(&self.layout, event)?, event.timestamp()))).collect::<Vec<_>>());
+            match fetched {
+                Ok(events) => steps.extend(events),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => warn!("Failed to read keystrokes from {:?}: {err}", device.name()),
+            }
+        }
+        steps
+    }
+}
+
+fn is_keyboard(device: &Device) -> bool {
+    let input_id = device.input_id();
+    //(the macro keys, including MR itself, are never part of a recording)
+    let is_g11_macro_interface = input_id.vendor() == usb_id::VENDOR_LOGITECH && input_id.product() == usb_id::PRODUCT_G11_MACRO;
+
+    !is_g11_macro_interface
+        && device.supported_keys().is_some_and(|keys| keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_ENTER))
+}
+
+/// The step corresponding to a single evdev event (if it is a keystroke that gets recorded)
+fn step(layout: &Layout, event: InputEvent) -> Option<Step> {
+    let EventSummary::Key(_, code, value) = event.destructure() else { return None };
+    if (KeyCode::BTN_0.0 ..= KeyCode::BTN_GEAR_UP.0).contains(&code.0) {
+        return None; //(mouse/joystick buttons, which are the concern of the pointer recording)
+    }
+    let direction = match value {
+        0 => Direction::Release,
+        1 => Direction::Press,
+        _ => return None, //(auto-repeat)
+    };
+
+    let Ok(keycode) = KeyMap::from_key_mapping(KeyMapping::Evdev(code.0)) else {
+        warn!("Not recording unknown key {code:?}");
+        return None;
+    };
+    let key = layout.key(keycode);
+    if key.is_none() {
+        warn!("Not recording {:?} ({code:?}), which cannot be replayed", keycode.id);
+    }
+    key.map(|key| Step::Key(key, direction))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Key;
+    use evdev::EventType;
+    use super::*;
+    use crate::record::mapping::keysym;
+
+    fn key_event(code: KeyCode, value: i32) -> InputEvent { InputEvent::new(EventType::KEY.0, code.0, value) }
+
+    #[test]
+    fn keystrokes() {
+        let layout = Layout::Us;
+
+        assert_eq!(step(&layout, key_event(KeyCode::KEY_A, 1)), Some(Step::Key(Key::Unicode('a'), Direction::Press)));
+        assert_eq!(step(&layout, key_event(KeyCode::KEY_A, 0)), Some(Step::Key(Key::Unicode('a'), Direction::Release)));
+        assert_eq!(step(&layout, key_event(KeyCode::KEY_A, 2)), None);
+        assert_eq!(step(&layout, key_event(KeyCode::KEY_LEFTSHIFT, 1)), Some(Step::Key(Key::LShift, Direction::Press)));
+        assert_eq!(step(&layout, key_event(KeyCode::KEY_RIGHTALT, 1)), Some(Step::Key(Key::Other(keysym::ISO_LEVEL3_SHIFT), Direction::Press)));
+        assert_eq!(step(&layout, key_event(KeyCode::KEY_F13, 1)), Some(Step::Key(Key::F13, Direction::Press)));
+    }
+
+    #[test]
+    fn ignores_buttons_and_other_events() {
+        let layout = Layout::Us;
+
+        assert_eq!(step(&layout, key_event(KeyCode::BTN_LEFT, 1)), None);
+        assert_eq!(step(&layout, InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0)), None);
+    }
+}
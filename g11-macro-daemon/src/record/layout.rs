@@ -0,0 +1,141 @@
+//! Interpretation of recorded keystrokes according to the user's keyboard layout,
+//! so that the characters scripted are those that were actually typed (see [`KeyboardLayout`])
+
+use std::collections::HashMap;
+use derive_more::{Display, Error};
+use keycode::KeyMap;
+use xkbcommon::xkb;
+use crate::config::{KeyboardLayout, XkbNames};
+use super::mapping::keycode_to_enigo_key;
+
+#[derive(Debug, Clone)]
+pub enum Layout {
+    /// Characters are those of [`keycode_to_enigo_key`]
+    Us,
+    Xkb {
+        /// The keysym of each key (by XKB keycode) when struck alone
+        keysyms: HashMap<u32, xkb::Keysym>,
+        /// The character produced by each of the above when struck with Shift
+        shifted: HashMap<char, char>,
+    },
+}
+impl Layout {
+    pub fn load(layout: &KeyboardLayout) -> Result<Self, LayoutError> {
+        match layout {
+            KeyboardLayout::Us => Ok(Self::Us),
+            KeyboardLayout::Xkb(names) => Self::load_xkb(names),
+        }
+    }
+
+    fn load_xkb(names: &XkbNames) -> Result<Self, LayoutError> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context, &names.rules, &names.model, &names.layout, &names.variant, names.options.clone(),
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        ).ok_or_else(|| LayoutError::Compiling(names.clone()))?;
+
+        let (mut keysyms, mut shifted) = (HashMap::new(), HashMap::new());
+        for keycode in keymap.min_keycode().raw() ..= keymap.max_keycode().raw() {
+            let level = |level| keymap.key_get_syms_by_level(xkb::Keycode::new(keycode), 0, level).first().copied();
+            let Some(base) = level(0) else { continue };
+            keysyms.insert(keycode, base);
+            if let (Some(base), Some(shift)) = (character(base), level(1).and_then(character)) {
+                shifted.entry(base).or_insert(shift);
+            }
+        }
+        Ok(Self::Xkb { keysyms, shifted })
+    }
+
+    /// The key to be scripted for the given keyboard key (if it is one that may be replayed).
+    /// Keys that produce a character are scripted as that (unshifted) character, whereas all others are unaffected by the layout
+    /// (as are any keys that the layout does not know of).
+    pub fn key(&self, keycode: KeyMap) -> Option<enigo::Key> {
+        let key = keycode_to_enigo_key(keycode);
+        match (self, key) {
+            (Self::Xkb { keysyms, .. }, Some(enigo::Key::Unicode(_))) =>
+                keysyms.get(&u32::from(keycode.xkb)).map(|keysym| match character(*keysym) {
+                    Some(c) => enigo::Key::Unicode(c),
+                    None => enigo::Key::Other(keysym.raw()), //(e.g. dead keys)
+                }).or(key),
+            _ => key,
+        }
+    }
+
+    /// The character typed by the key for `c` while Shift is held (if known)
+    pub fn shifted(&self, c: char) -> Option<char> {
+        match self {
+            Self::Us if c.is_ascii_lowercase() => Some(c.to_ascii_uppercase()),
+            Self::Us => Some(match c {
+                '`' => '~', '1' => '!', '2' => '@', '3' => '#', '4' => '$', '5' => '%',
+                '6' => '^', '7' => '&', '8' => '*', '9' => '(', '0' => ')', '-' => '_', '=' => '+',
+                '[' => '{', ']' => '}', '\\' => '|', ';' => ':', '\'' => '"', ',' => '<', '.' => '>', '/' => '?',
+                other => other,
+            }),
+            Self::Xkb { shifted, .. } => shifted.get(&c).copied(),
+        }
+    }
+}
+
+fn character(keysym: xkb::Keysym) -> Option<char> {
+    char::from_u32(xkb::keysym_to_utf32(keysym))
+        .filter(|c| *c != '\0' && !c.is_control())
+}
+
+/// Errors that may arise during [`Layout::load`]
+#[derive(Debug, Display, Error)]
+pub enum LayoutError {
+    #[display("Unable to compile the XKB keymap for {_0:?}")]
+    Compiling(#[error(not(source))] XkbNames),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Key;
+    use super::*;
+
+    fn key(layout: &Layout, usage: u16) -> Option<Key> {
+        layout.key(KeyMap::from_usb_code(0x07, usage).expect("known usage"))
+    }
+
+    fn xkb(layout: &str) -> Layout {
+        Layout::load(&KeyboardLayout::Xkb(XkbNames { layout: layout.into(), ..XkbNames::default() }))
+            .expect("keymap compiles")
+    }
+
+    #[test]
+    fn us() {
+        let layout = Layout::load(&KeyboardLayout::Us).expect("always loads");
+
+        assert_eq!(key(&layout, 0x1c), Some(Key::Unicode('y')));
+        assert_eq!(layout.shifted('7'), Some('&'));
+    }
+
+    #[test]
+    fn xkb_us_matches_us() {
+        let (us, xkb) = (Layout::Us, xkb("us"));
+
+        for usage in 0x04 ..= 0x38 {
+            assert_eq!(key(&xkb, usage), key(&us, usage), "usage {usage:#04x}");
+        }
+        for c in "abcxyz1234567890-=[];',./`\\".chars() {
+            assert_eq!(xkb.shifted(c), us.shifted(c), "{c}");
+        }
+    }
+
+    #[test]
+    fn xkb_german() {
+        let layout = xkb("de");
+
+        assert_eq!(key(&layout, 0x1c), Some(Key::Unicode('z'))); //(the US 'y' key)
+        assert_eq!(key(&layout, 0x33), Some(Key::Unicode('ö'))); //(the US ';' key)
+        assert_eq!(key(&layout, 0x28), Some(Key::Return));
+        assert_eq!(layout.shifted('7'), Some('/'));
+        assert_eq!(layout.shifted('ö'), Some('Ö'));
+    }
+
+    #[test]
+    fn unknown_xkb_layout() {
+        assert!(Layout::load(&KeyboardLayout::Xkb(XkbNames { layout: "no-such-layout".into(), ..XkbNames::default() })).is_err());
+    }
+}
@@ -1,5 +1,43 @@
+//! Translation of USB HID usages (as reported by the standard keyboard interface) into [`enigo::Key`]s.
+//!
+//! Keys that [`enigo::Key`] has no variant for are given as [`enigo::Key::Other`] with their X11 keysym (see [`keysym`]),
+//! and characters are those of a US layout (see [`Layout`](super::layout::Layout) for others).
+
 use keycode::{KeyMappingId, KeyModifiers};
 
+/// X11 keysyms (from `X11/keysymdef.h` and `X11/XF86keysym.h`) for keys that [`enigo::Key`] has no variant for
+pub mod keysym {
+    pub const MENU: u32 = 0xff67;
+    pub const SUPER_R: u32 = 0xffec;
+    /// AltGr
+    pub const ISO_LEVEL3_SHIFT: u32 = 0xfe03;
+    pub const KP_ENTER: u32 = 0xff8d;
+    pub const KP_EQUAL: u32 = 0xffbd;
+    pub const KP_SEPARATOR: u32 = 0xffac;
+    pub const HIRAGANA_KATAKANA: u32 = 0xff27;
+    pub const HENKAN: u32 = 0xff23;
+    pub const MUHENKAN: u32 = 0xff22;
+    pub const KATAKANA: u32 = 0xff26;
+    pub const HIRAGANA: u32 = 0xff25;
+    pub const ZENKAKU_HANKAKU: u32 = 0xff2a;
+    pub const XF86_POWER_OFF: u32 = 0x1008ff2a;
+    pub const XF86_CUT: u32 = 0x1008ff58;
+    pub const XF86_COPY: u32 = 0x1008ff57;
+    pub const XF86_PASTE: u32 = 0x1008ff6d;
+    pub const XF86_EJECT: u32 = 0x1008ff2c;
+    pub const XF86_MAIL: u32 = 0x1008ff19;
+    pub const XF86_MY_COMPUTER: u32 = 0x1008ff33;
+    pub const XF86_CALCULATOR: u32 = 0x1008ff1d;
+    pub const XF86_SEARCH: u32 = 0x1008ff1b;
+    pub const XF86_HOME_PAGE: u32 = 0x1008ff18;
+    pub const XF86_BACK: u32 = 0x1008ff26;
+    pub const XF86_FORWARD: u32 = 0x1008ff27;
+    pub const XF86_STOP: u32 = 0x1008ff28;
+    pub const XF86_REFRESH: u32 = 0x1008ff29;
+    pub const XF86_FAVORITES: u32 = 0x1008ff30;
+}
+
+/// The key to be scripted for the given keyboard key (if it is one that may be replayed)
 pub const fn keycode_to_enigo_key(keycode: keycode::KeyMap) -> Option<enigo::Key> {
     Some(match keycode.id {
         KeyMappingId::Escape => enigo::Key::Escape,
@@ -15,6 +53,18 @@ pub const fn keycode_to_enigo_key(keycode: keycode::KeyMap) -> Option<enigo::Key
         KeyMappingId::F10 => enigo::Key::F10,
         KeyMappingId::F11 => enigo::Key::F11,
         KeyMappingId::F12 => enigo::Key::F12,
+        KeyMappingId::F13 => enigo::Key::F13,
+        KeyMappingId::F14 => enigo::Key::F14,
+        KeyMappingId::F15 => enigo::Key::F15,
+        KeyMappingId::F16 => enigo::Key::F16,
+        KeyMappingId::F17 => enigo::Key::F17,
+        KeyMappingId::F18 => enigo::Key::F18,
+        KeyMappingId::F19 => enigo::Key::F19,
+        KeyMappingId::F20 => enigo::Key::F20,
+        KeyMappingId::F21 => enigo::Key::F21,
+        KeyMappingId::F22 => enigo::Key::F22,
+        KeyMappingId::F23 => enigo::Key::F23,
+        KeyMappingId::F24 => enigo::Key::F24,
         KeyMappingId::PrintScreen => enigo::Key::PrintScr,
         KeyMappingId::ScrollLock => enigo::Key::ScrollLock,
         KeyMappingId::Pause => enigo::Key::Pause,
@@ -24,7 +74,7 @@ pub const fn keycode_to_enigo_key(keycode: keycode::KeyMap) -> Option<enigo::Key
         KeyMappingId::CapsLock => enigo::Key::CapsLock,
         KeyMappingId::ShiftLeft => enigo::Key::LShift,
         KeyMappingId::ControlLeft => enigo::Key::LControl,
-        KeyMappingId::Super => enigo::Key::Meta,
+        KeyMappingId::MetaLeft => enigo::Key::Meta,
         KeyMappingId::AltLeft => enigo::Key::Alt,
         KeyMappingId::Space => enigo::Key::Space,
 
@@ -34,16 +84,19 @@ pub const fn keycode_to_enigo_key(keycode: keycode::KeyMap) -> Option<enigo::Key
         KeyMappingId::BracketLeft => enigo::Key::Unicode('['),
         KeyMappingId::BracketRight => enigo::Key::Unicode(']'),
         KeyMappingId::Backslash => enigo::Key::Unicode('\\'),
+        KeyMappingId::IntlHash => enigo::Key::Unicode('\\'), //(the key beside Enter on ISO keyboards, which types backslash on a US layout)
         KeyMappingId::Semicolon => enigo::Key::Unicode(';'),
         KeyMappingId::Quote => enigo::Key::Unicode('\''),
         KeyMappingId::Enter => enigo::Key::Return,
         KeyMappingId::Comma => enigo::Key::Unicode(','),
         KeyMappingId::Period => enigo::Key::Unicode('.'),
         KeyMappingId::Slash => enigo::Key::Unicode('/'),
+        KeyMappingId::IntlBackslash => enigo::Key::Unicode('<'), //(the key beside the left Shift on ISO keyboards, which types less-than on a US layout)
         KeyMappingId::ShiftRight => enigo::Key::RShift,
         KeyMappingId::ControlRight => enigo::Key::RControl,
-        KeyMappingId::ContextMenu => enigo::Key::LMenu,
-        KeyMappingId::AltRight => enigo::Key::Alt,
+        KeyMappingId::ContextMenu => enigo::Key::Other(keysym::MENU),
+        KeyMappingId::MetaRight => enigo::Key::Other(keysym::SUPER_R),
+        KeyMappingId::AltRight => enigo::Key::Other(keysym::ISO_LEVEL3_SHIFT),
 
         KeyMappingId::Digit1 => enigo::Key::Unicode('1'),
         KeyMappingId::Digit2 => enigo::Key::Unicode('2'),
@@ -113,18 +166,142 @@ pub const fn keycode_to_enigo_key(keycode: keycode::KeyMap) -> Option<enigo::Key
         KeyMappingId::NumpadSubtract => enigo::Key::Subtract,
         KeyMappingId::NumpadAdd => enigo::Key::Add,
         KeyMappingId::NumpadDecimal => enigo::Key::Decimal,
+        KeyMappingId::NumpadEnter => enigo::Key::Other(keysym::KP_ENTER),
+        KeyMappingId::NumpadEqual => enigo::Key::Other(keysym::KP_EQUAL),
+        KeyMappingId::NumpadComma => enigo::Key::Other(keysym::KP_SEPARATOR),
+        KeyMappingId::NumpadParenLeft => enigo::Key::Unicode('('),
+        KeyMappingId::NumpadParenRight => enigo::Key::Unicode(')'),
+
+        KeyMappingId::IntlRo => enigo::Key::Unicode('\\'),
+        KeyMappingId::IntlYen => enigo::Key::Unicode('\\'),
+        KeyMappingId::KanaMode => enigo::Key::Other(keysym::HIRAGANA_KATAKANA),
+        KeyMappingId::Convert => enigo::Key::Other(keysym::HENKAN),
+        KeyMappingId::NonConvert => enigo::Key::Other(keysym::MUHENKAN),
+        KeyMappingId::Lang1 => enigo::Key::Hangul,
+        KeyMappingId::Lang2 => enigo::Key::Hanja,
+        KeyMappingId::Lang3 => enigo::Key::Other(keysym::KATAKANA),
+        KeyMappingId::Lang4 => enigo::Key::Other(keysym::HIRAGANA),
+        KeyMappingId::Lang5 => enigo::Key::Other(keysym::ZENKAKU_HANKAKU),
+
+        KeyMappingId::Power => enigo::Key::Other(keysym::XF86_POWER_OFF),
+        KeyMappingId::Open => enigo::Key::Execute,
+        KeyMappingId::Help => enigo::Key::Help,
+        KeyMappingId::Select => enigo::Key::Select,
+        KeyMappingId::Again => enigo::Key::Redo,
+        KeyMappingId::Undo => enigo::Key::Undo,
+        KeyMappingId::Cut => enigo::Key::Other(keysym::XF86_CUT),
+        KeyMappingId::Copy => enigo::Key::Other(keysym::XF86_COPY),
+        KeyMappingId::Paste => enigo::Key::Other(keysym::XF86_PASTE),
+        KeyMappingId::Find => enigo::Key::Find,
+        KeyMappingId::Abort => enigo::Key::Cancel,
+
+        KeyMappingId::VolumeMute => enigo::Key::VolumeMute,
+        KeyMappingId::VolumeUp => enigo::Key::VolumeUp,
+        KeyMappingId::VolumeDown => enigo::Key::VolumeDown,
+        KeyMappingId::MediaPlayPause => enigo::Key::MediaPlayPause,
+        KeyMappingId::MediaStop => enigo::Key::MediaStop,
+        KeyMappingId::MediaTrackNext => enigo::Key::MediaNextTrack,
+        KeyMappingId::MediaTrackPrevious => enigo::Key::MediaPrevTrack,
+        KeyMappingId::Eject => enigo::Key::Other(keysym::XF86_EJECT),
+        KeyMappingId::LaunchMail => enigo::Key::Other(keysym::XF86_MAIL),
+        KeyMappingId::LaunchApp1 => enigo::Key::Other(keysym::XF86_MY_COMPUTER),
+        KeyMappingId::LaunchApp2 => enigo::Key::Other(keysym::XF86_CALCULATOR),
+        KeyMappingId::BrowserSearch => enigo::Key::Other(keysym::XF86_SEARCH),
+        KeyMappingId::BrowserHome => enigo::Key::Other(keysym::XF86_HOME_PAGE),
+        KeyMappingId::BrowserBack => enigo::Key::Other(keysym::XF86_BACK),
+        KeyMappingId::BrowserForward => enigo::Key::Other(keysym::XF86_FORWARD),
+        KeyMappingId::BrowserStop => enigo::Key::Other(keysym::XF86_STOP),
+        KeyMappingId::BrowserRefresh => enigo::Key::Other(keysym::XF86_REFRESH),
+        KeyMappingId::BrowserFavorites => enigo::Key::Other(keysym::XF86_FAVORITES),
 
         _ => return None,
     })
 }
 
-pub fn key_modifier_to_enigo_key(key_modifier: KeyModifiers) -> Option<enigo::Key> {
-    if key_modifier.is_empty() { None }
-    else if key_modifier.contains(KeyModifiers::ControlLeft)  { Some(enigo::Key::LControl) }
-    else if key_modifier.contains(KeyModifiers::ControlRight) { Some(enigo::Key::RControl) }
-    else if key_modifier.contains(KeyModifiers::ShiftLeft)  { Some(enigo::Key::LShift) }
-    else if key_modifier.contains(KeyModifiers::ShiftRight) { Some(enigo::Key::RShift) }
-    else if key_modifier.intersects(KeyModifiers::AltLeft | KeyModifiers::AltRight) { Some(enigo::Key::Alt) }
-    else if key_modifier.intersects(KeyModifiers::MetaLeft | KeyModifiers::MetaRight) { Some(enigo::Key::Meta) }
-    else { unreachable!("exhaustive") }
+/// Each individual modifier bit of a report, in the order that their changes are reported
+/// (matching [`keycode_to_enigo_key`] for the corresponding keys)
+pub const KEY_MODIFIERS: [(KeyModifiers, enigo::Key); 8] = [
+    (KeyModifiers::ControlLeft, enigo::Key::LControl),
+    (KeyModifiers::ShiftLeft, enigo::Key::LShift),
+    (KeyModifiers::AltLeft, enigo::Key::Alt),
+    (KeyModifiers::MetaLeft, enigo::Key::Meta),
+    (KeyModifiers::ControlRight, enigo::Key::RControl),
+    (KeyModifiers::ShiftRight, enigo::Key::RShift),
+    (KeyModifiers::AltRight, enigo::Key::Other(keysym::ISO_LEVEL3_SHIFT)),
+    (KeyModifiers::MetaRight, enigo::Key::Other(keysym::SUPER_R)),
+];
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Key;
+    use keycode::KeyMap;
+    use super::*;
+
+    /// Keys of the keyboard page that are deliberately left unmapped (they have no common X11 keysym)
+    const UNMAPPED: &[KeyMappingId] = &[
+        KeyMappingId::Props, KeyMappingId::NumpadBackspace, KeyMappingId::NumpadSignChange,
+        KeyMappingId::NumpadClear, KeyMappingId::NumpadClearEntry,
+        KeyMappingId::NumpadMemoryStore, KeyMappingId::NumpadMemoryRecall, KeyMappingId::NumpadMemoryClear,
+        KeyMappingId::NumpadMemoryAdd, KeyMappingId::NumpadMemorySubtract,
+    ];
+
+    fn key_for(usage_page: u16, usage: u16) -> Option<Key> {
+        keycode_to_enigo_key(KeyMap::from_usb_code(usage_page, usage).expect("known usage"))
+    }
+
+    #[test]
+    fn maps_keys() {
+        for (usage_page, usage, key) in [
+            (0x07, 0x04, Key::Unicode('a')),
+            (0x07, 0x28, Key::Return),
+            (0x07, 0x58, Key::Other(keysym::KP_ENTER)),
+            (0x07, 0x65, Key::Other(keysym::MENU)),
+            (0x07, 0x68, Key::F13),
+            (0x07, 0x73, Key::F24),
+            (0x07, 0x7f, Key::VolumeMute),
+            (0x07, 0x87, Key::Unicode('\\')),
+            (0x07, 0x88, Key::Other(keysym::HIRAGANA_KATAKANA)),
+            (0x07, 0x90, Key::Hangul),
+            (0x07, 0x91, Key::Hanja),
+            (0x07, 0xe2, Key::Alt),
+            (0x07, 0xe3, Key::Meta),
+            (0x07, 0xe6, Key::Other(keysym::ISO_LEVEL3_SHIFT)),
+            (0x07, 0xe7, Key::Other(keysym::SUPER_R)),
+            (0x0c, 0xb5, Key::MediaNextTrack),
+            (0x0c, 0xb6, Key::MediaPrevTrack),
+            (0x0c, 0xcd, Key::MediaPlayPause),
+        ] {
+            assert_eq!(key_for(usage_page, usage), Some(key), "usage {usage_page:#04x}:{usage:#04x}");
+        }
+    }
+
+    #[test]
+    fn maps_every_keyboard_key() {
+        for usage in 0x04..=0xe7 {
+            let Ok(keymap) = KeyMap::from_usb_code(0x07, usage) else { continue };
+            assert!(
+                keycode_to_enigo_key(keymap).is_some() || UNMAPPED.contains(&keymap.id),
+                "{:?} (usage {usage:#04x}) is not mapped", keymap.id,
+            );
+        }
+    }
+
+    #[test]
+    fn modifiers_match_their_keys() {
+        for (modifier, key) in KEY_MODIFIERS {
+            let usage = 0xe0 + modifier.bits().trailing_zeros() as u16;
+            let keymap = KeyMap::from_usb_code(0x07, usage).expect("known usage");
+            assert_eq!(keymap.modifier, Some(modifier));
+            assert_eq!(keycode_to_enigo_key(keymap), Some(key), "{modifier:?}");
+        }
+    }
+
+    #[test]
+    fn distinguishes_left_and_right_modifiers() {
+        let keys: Vec<Key> = KEY_MODIFIERS.iter().map(|(_, key)| *key).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{key:?} is used for more than one modifier");
+        }
+    }
 }
@@ -1,71 +1,195 @@
-use std::time::{Duration, Instant};
-use enigo::Direction;
+use std::time::{Duration, Instant, SystemTime};
+use enigo::{Coordinate, Direction};
 use hidapi::{HidApi, HidDevice, HidError, HidResult};
 use log::{error, warn};
 use g11_macro_keys::{usb_id, Action, Event};
-use crate::{config::KeyBinding, steps::Step};
+use crate::{config::{KeyBinding, RecordSource, RecordTiming, RecordingSettings}, steps::Step};
+use layout::Layout;
 
 mod standard_keys;
+mod evdev_keys;
 mod mapping;
+mod layout;
+mod pointer;
+mod coalesce;
 
 const MR_LED_BLINK_PERIOD: Duration = Duration::from_millis(500);
+/// How long MR must be held to cancel a recording (rather than save it)
+const MR_HOLD_TO_CANCEL: Duration = Duration::from_secs(1);
+/// How soon MR must be pressed again (after the press that entered 'record' state) to undo the latest recording instead
+const MR_DOUBLE_PRESS: Duration = Duration::from_millis(400);
 
-/// Places the application in 'record' state, where the user must:
+/// A recorded step, along with when its event occurred (as stamped by the kernel for evdev, or as read for hidraw)
+type TimedStep = (Step, SystemTime);
+
+/// How a call to [`run_event_loop`] ended
+#[derive(Debug)]
+pub enum Outcome {
+    /// The newly recorded binding, which replaces any existing binding for its key
+    Recorded(KeyBinding),
+    /// The user cancelled the recording, so any existing binding is left as-is
+    Cancelled,
+    /// The user double-pressed MR, to undo the latest recording (see [`crate::config::undo_recorded_macro`])
+    Undo,
+    /// Nothing was recorded (no G key was chosen, nothing was typed, or an error occurred)
+    Nothing,
+}
+
+/// Places the application in 'record' state (unless MR is promptly pressed again, to [`Outcome::Undo`] the latest recording),
+/// where the user must:
 /// 1. Press a 'G' key, for which the recorded macro will be associated
-/// 2. Perform any number of regular keyboard interactions that will be used as the script
+///    (within bank `m`, unless an 'M' key is pressed beforehand to choose another bank).
+///    Holding the 'G' key for at least [`RecordingSettings::release_hold_millis`] binds the macro to its release instead.
+/// 2. Perform any number of regular keyboard interactions (and, if [`RecordingSettings::pointer`], mouse interactions)
+///    that will be used as the script
+///    (with the delays between keystrokes scripted according to [`RecordingSettings::timing`],
+///    and typed text folded into [`Step::Text`], as typed on the [`RecordingSettings::layout`])
 /// 3. Press the 'MR' key to stop recording
+///    (or cancel it, by holding 'MR' for [`MR_HOLD_TO_CANCEL`] or pressing the chosen 'G' key again)
 ///
 /// LED feedback:
-/// 1. The `MR` LED remains solid until the user chooses a 'G' key
+/// 1. The `MR` LED remains solid until the user chooses a 'G' key (going out once a 'G' key has been held long enough to bind its release),
+///    with the 'M' LED showing the bank being recorded into
 /// 2. The `MR` LED will then blink continuously during the recording of regular keys
-/// 3. The `MR` LED is extinguished before returning from this method
+/// 3. The `MR` LED is extinguished before returning from this method (and the 'M' LED of bank `m` is restored)
 pub fn run_event_loop(
     api: &HidApi,
     hid_macro: &HidDevice,
     state_macro: &mut g11_macro_keys::State,
     m: u8,
-) -> Option<KeyBinding> {
+    settings: &RecordingSettings,
+) -> Outcome {
     let _ = state_macro.light_led(g11_macro_keys::Key::MR)
         .and_then(|usb_report| hid_macro.send_feature_report(&usb_report).inspect_err(warn_led_failure).ok());
 
-    let key_binding =
-        choose_g_key(hid_macro, state_macro)
-            .inspect_err(|err| error!("Aborting macro recording due to an error when choosing the G key: {err:#?}"))
-            .ok().flatten()
-            .and_then(|g|
-                record_script(api, hid_macro, state_macro)
+    let selection = Selection::new(m, Duration::from_millis(settings.release_hold_millis), Instant::now());
+    let outcome =
+        match choose_target(hid_macro, state_macro, selection)
+            .inspect_err(|err| error!("Aborting macro recording due to an error when choosing the G key: {err:#?}")) {
+            Ok(Choice::Chosen(target)) =>
+                record_script(api, hid_macro, state_macro, settings, target)
                     .inspect_err(|err| error!("Aborting macro recording due to an error when scripting: {err:#?}"))
-                    .ok().flatten()
-                    .map(|script| KeyBinding { m, g, on: Direction::Press, script })
-            );
+                    .unwrap_or(Outcome::Nothing),
+            Ok(Choice::Undo) => Outcome::Undo,
+            _ => Outcome::Nothing,
+        };
 
-    let _ = state_macro.extinguish_led(g11_macro_keys::Key::MR)
+    let _ = state_macro.set_exact_lit_leds(&[g11_macro_keys::Key::M(m)])
         .and_then(|usb_report| hid_macro.send_feature_report(&usb_report).inspect_err(warn_led_failure).ok());
 
-    key_binding
+    outcome
 }
 
-fn choose_g_key(hid_macro: &HidDevice, state_macro: &mut g11_macro_keys::State) -> HidResult<Option<u8>> {
+/// Returns the final [`Choice`] (being neither [`Choice::Undecided`] nor [`Choice::Bank`])
+fn choose_target(hid_macro: &HidDevice, state_macro: &mut g11_macro_keys::State, mut selection: Selection) -> HidResult<Choice> {
     let mut usb_buf = [0_u8; 9];
     loop {
-        assert_eq!(hid_macro.read(&mut usb_buf).expect("could not read from device"), 9);
-        match state_macro.try_consume_event(&usb_buf) {
-            Ok(Event { action: Action::Released, key: g11_macro_keys::Key::G(g_key) }) => return Ok(Some(g_key)),
-            Ok(Event { action: Action::Released, key: g11_macro_keys::Key::MR }) => return Ok(None),
-            _ => {} //Ignore all other keys at this time
+        //(wake up periodically while a G key is held, to signal once it has been held long enough)
+        let timeout_millis = if selection.g_pressed.is_some() { 20 } else { -1 };
+        let event = match hid_macro.read_timeout(&mut usb_buf, timeout_millis)? {
+            9 => state_macro.try_consume_event(&usb_buf).ok(),
+            _ => None,
+        };
+        let led_report = match event.map(|event| selection.consume(event, Instant::now())) {
+            Some(choice @ (Choice::Chosen(_) | Choice::Cancelled | Choice::Undo)) => return Ok(choice),
+            Some(Choice::Bank(m)) => state_macro.set_exact_lit_leds(&[g11_macro_keys::Key::M(m), g11_macro_keys::Key::MR]),
+            Some(Choice::Undecided) | None if selection.is_held_for_release(Instant::now()) => state_macro.extinguish_led(g11_macro_keys::Key::MR),
+            Some(Choice::Undecided) | None => None,
+        };
+        let _ = led_report.and_then(|usb_report| hid_macro.send_feature_report(&usb_report).inspect_err(warn_led_failure).ok());
+    }
+}
+
+/// Where the macro being recorded will be bound
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Target {
+    m: u8,
+    g: u8,
+    on: Direction,
+}
+
+/// The user's progress in choosing the [`Target`]
+#[derive(Debug)]
+struct Selection {
+    m: u8,
+    /// The G key currently held, and since when
+    g_pressed: Option<(u8, Instant)>,
+    /// How long the G key must be held for the macro to be bound to its release
+    release_hold: Duration,
+    /// When 'record' state was entered
+    started_at: Instant,
+    /// Whether MR was pressed again soon enough to be a double-press
+    double_pressed: bool,
+}
+#[derive(Debug, PartialEq)]
+enum Choice {
+    Undecided,
+    /// Another bank has been chosen (but not yet the G key)
+    Bank(u8),
+    Chosen(Target),
+    Cancelled,
+    Undo,
+}
+impl Selection {
+    fn new(m: u8, release_hold: Duration, started_at: Instant) -> Self {
+        Self { m, g_pressed: None, release_hold, started_at, double_pressed: false }
+    }
+
+
+    fn consume(&mut self, event: Event, now: Instant) -> Choice {
+        match event {
+            Event { action: Action::Pressed, key: g11_macro_keys::Key::M(m) } => {
+                self.m = m;
+                Choice::Bank(m)
+            }
+            Event { action: Action::Pressed, key: g11_macro_keys::Key::G(g) } => {
+                self.g_pressed = Some((g, now));
+                Choice::Undecided
+            }
+            Event { action: Action::Released, key: g11_macro_keys::Key::G(g) } => {
+                let on = match self.g_pressed.take() {
+                    Some((pressed, since)) if pressed == g && now - since >= self.release_hold => Direction::Release,
+                    _ => Direction::Press,
+                };
+                Choice::Chosen(Target { m: self.m, g, on })
+            }
+            Event { action: Action::Pressed, key: g11_macro_keys::Key::MR } => {
+                self.double_pressed = now - self.started_at <= MR_DOUBLE_PRESS;
+                Choice::Undecided
+            }
+            Event { action: Action::Released, key: g11_macro_keys::Key::MR } if self.double_pressed => Choice::Undo,
+            Event { action: Action::Released, key: g11_macro_keys::Key::MR } => Choice::Cancelled,
+            _ => Choice::Undecided, //Ignore all other keys at this time
         }
     }
+
+    fn is_held_for_release(&self, now: Instant) -> bool {
+        self.g_pressed.is_some_and(|(_, since)| now - since >= self.release_hold)
+    }
 }
 
-fn record_script(api: &HidApi, hid_macro: &HidDevice, state_macro: &mut g11_macro_keys::State) -> HidResult<Option<Vec<Step>>> {
-    let mut state_104key = standard_keys::State::new();
-    let hid_104key = api.open(usb_id::VENDOR_LOGITECH, usb_id::PRODUCT_G11_STANDARD)
-        .and_then(|device| device.set_blocking_mode(false).map(|()| device))?;
+fn record_script(
+    api: &HidApi,
+    hid_macro: &HidDevice,
+    state_macro: &mut g11_macro_keys::State,
+    settings: &RecordingSettings,
+    Target { m, g, on }: Target,
+) -> HidResult<Outcome> {
+    let layout = Layout::load(&settings.layout)
+        .unwrap_or_else(|err| {
+            error!("Recording with the US layout instead, due to an error loading the configured layout: {err}");
+            Layout::Us
+        });
+    let mut keyboard = Keyboard::open(api, &settings.source, layout)?;
+    let mut pointer = settings.pointer.then(pointer::Pointer::open);
     let mut usb_buf = [0_u8; 9];
 
     let (mut next_blink, mut next_blink_at) = (false, Instant::now());
 
-    let mut script = vec![];
+    let mut recording = Recording::default();
+    //Once MR (or the chosen G key) is pressed, anything else belongs to that interaction (rather than the macro)
+    let mut stopping = false;
+    let mut mr_pressed_at = None;
     loop {
         if Instant::now() >= next_blink_at {
             let op =
@@ -77,28 +201,305 @@ fn record_script(api: &HidApi, hid_macro: &HidDevice, state_macro: &mut g11_macr
             next_blink_at += MR_LED_BLINK_PERIOD;
         }
 
-        if hid_104key.read(&mut usb_buf)? == 8 {
-            let step = state_104key.try_consume_event(&usb_buf)
-                .map_err(|err| HidError::HidApiError { message: err.to_string() })?;
+        let mut steps = keyboard.poll()?;
+        steps.extend(pointer.as_mut().map(pointer::Pointer::poll).unwrap_or_default());
+        //(the keyboard's and pointer's events are interleaved as they occurred)
+        steps.sort_by_key(|(_, at)| *at);
+        for (step, at) in steps.into_iter().filter(|_| !stopping) {
+            recording.record(settings.timing, step, at);
+        }
 
-            match (script.last_mut(), step) {
-                (Some(Step::Key(prev_key, prev_dir@Direction::Press)), Some(Step::Key(new_key, Direction::Release)))
-                  if *prev_key == new_key =>
-                    *prev_dir = Direction::Click,
-                (_, Some(step)) =>
-                    script.push(step),
-                (_, None) => {}
+        if hid_macro.read_timeout(&mut usb_buf, 10)? == 9 {
+            match state_macro.try_consume_event(&usb_buf) {
+                Ok(Event { action: Action::Pressed, key: g11_macro_keys::Key::MR }) => {
+                    stopping = true;
+                    mr_pressed_at = Some(Instant::now());
+                }
+                Ok(Event { action: Action::Released, key: g11_macro_keys::Key::MR })
+                  if mr_pressed_at.is_some_and(|pressed_at| pressed_at.elapsed() >= MR_HOLD_TO_CANCEL) =>
+                    return Ok(Outcome::Cancelled),
+                Ok(Event { action: Action::Released, key: g11_macro_keys::Key::MR }) => break,
+                //(the release is awaited too, so that it does not go on to trigger the G key's existing binding)
+                Ok(Event { action: Action::Pressed, key: g11_macro_keys::Key::G(pressed) }) if pressed == g => stopping = true,
+                Ok(Event { action: Action::Released, key: g11_macro_keys::Key::G(released) }) if released == g && stopping =>
+                    return Ok(Outcome::Cancelled),
+                _ => {}
+            }
+        }
+    }
+    let script = recording.finish();
+    Ok(if script.is_empty() { Outcome::Nothing } else {
+        Outcome::Recorded(KeyBinding::new(m, g, on, coalesce::coalesce_text(script, keyboard.layout())))
+    })
+}
+
+/// The source of the keystrokes being recorded (see [`RecordSource`])
+enum Keyboard {
+    Hidraw(HidDevice, standard_keys::State),
+    Evdev(evdev_keys::Keyboards),
+}
+impl Keyboard {
+    fn open(api: &HidApi, source: &RecordSource, layout: Layout) -> HidResult<Self> {
+        Ok(match source {
+            RecordSource::Hidraw => {
+                let hid_104key = api.open(usb_id::VENDOR_LOGITECH, usb_id::PRODUCT_G11_STANDARD)
+                    .and_then(|device| device.set_blocking_mode(false).map(|()| device))?;
+                Self::Hidraw(hid_104key, standard_keys::State::new(layout))
+            }
+            RecordSource::Evdev => Self::Evdev(evdev_keys::Keyboards::open_all(layout)),
+            RecordSource::EvdevDevice(path) => Self::Evdev(
+                evdev_keys::Keyboards::open(path, layout).map_err(|error| HidError::IoError { error })?
+            ),
+        })
+    }
+
+    /// Returns the steps for every keystroke since the last poll (without blocking), each stamped with when it occurred
+    fn poll(&mut self) -> HidResult<Vec<TimedStep>> {
+        match self {
+            Self::Hidraw(hid_104key, state_104key) => {
+                //(every pending report is read, so that each is stamped as soon as possible after it arrived)
+                let mut usb_buf = [0_u8; 9];
+                let mut steps = vec![];
+                while hid_104key.read(&mut usb_buf)? == 8 {
+                    let at = SystemTime::now();
+                    let consumed = state_104key.try_consume_event(&usb_buf)
+                        .map_err(|err| HidError::HidApiError { message: err.to_string() })?;
+                    steps.extend(consumed.into_iter().map(|step| (step, at)));
+                }
+                Ok(steps)
+            }
+            Self::Evdev(keyboards) => Ok(keyboards.poll()),
+        }
+    }
+
+    fn layout(&self) -> &Layout {
+        match self {
+            Self::Hidraw(_, state_104key) => state_104key.layout(),
+            Self::Evdev(keyboards) => keyboards.layout(),
+        }
+    }
+}
+
+/// A script in the midst of being recorded
+#[derive(Debug, Default)]
+struct Recording {
+    script: Vec<Step>,
+    /// When the event of the previous step occurred
+    last_step_at: Option<SystemTime>,
+    /// The release of each key/button that has been pressed during the recording (and not yet released)
+    held: Vec<Step>,
+}
+impl Recording {
+    /// Appends the step to the script, preceded by any delay since the previous step (as per the [`RecordTiming`]), `at` being when its event occurred.
+    /// A release directly following the press of the same key/button is merged into a click,
+    /// and consecutive relative movements/scrolls are merged into one.
+    /// The release of a key/button that was already held when recording began is dropped.
+    fn record(&mut self, timing: RecordTiming, step: Step, at: SystemTime) {
+        match &step {
+            Step::Key(key, Direction::Press) => self.held.push(Step::Key(*key, Direction::Release)),
+            Step::Button(button, Direction::Press) => self.held.push(Step::Button(*button, Direction::Release)),
+            release @ (Step::Key(_, Direction::Release) | Step::Button(_, Direction::Release)) => {
+                let Some(index) = self.held.iter().position(|held| held == release) else { return };
+                self.held.remove(index);
+            }
+            _ => {}
+        }
+
+        //Only the gaps before each press are of interest (not how long each key is held, nor how long the pointer takes to move)
+        //(events from different devices may be stamped slightly out of order, in which case there is no gap)
+        if let Step::Key(_, Direction::Press) | Step::Button(_, Direction::Press) = step {
+            let elapsed = self.last_step_at.map(|last_step_at| at.duration_since(last_step_at).unwrap_or_default());
+            if let Some(millis) = elapsed.and_then(|elapsed| timing.delay_millis(elapsed)) {
+                self.script.push(Step::Sleep(millis));
             }
         }
+        self.last_step_at = Some(at);
 
-        if hid_macro.read_timeout(&mut usb_buf, 10)? == 9
-            && matches!(state_macro.try_consume_event(&usb_buf), Ok(Event { action: Action::Released, key: g11_macro_keys::Key::MR })) {
-            break;
+        match (self.script.last_mut(), step) {
+            (Some(Step::Key(prev_key, prev_dir@Direction::Press)), Step::Key(new_key, Direction::Release))
+              if *prev_key == new_key =>
+                *prev_dir = Direction::Click,
+            (Some(Step::Button(prev_button, prev_dir@Direction::Press)), Step::Button(new_button, Direction::Release))
+              if *prev_button == new_button =>
+                *prev_dir = Direction::Click,
+            (Some(Step::MoveMouse(prev_x, prev_y, Coordinate::Rel)), Step::MoveMouse(x, y, Coordinate::Rel)) => {
+                *prev_x += x;
+                *prev_y += y;
+            }
+            (Some(Step::Scroll(prev_length, prev_axis)), Step::Scroll(length, axis)) if *prev_axis == axis =>
+                *prev_length += length,
+            (_, step) =>
+                self.script.push(step),
+        }
+    }
+
+    /// Completes the script by releasing anything still held (most recently pressed first),
+    /// so that replaying it never leaves a key stuck down
+    fn finish(mut self) -> Vec<Step> {
+        while let Some(release) = self.held.pop() {
+            match (self.script.last_mut(), release) {
+                (Some(Step::Key(prev_key, prev_dir@Direction::Press)), Step::Key(key, _)) if *prev_key == key =>
+                    *prev_dir = Direction::Click,
+                (Some(Step::Button(prev_button, prev_dir@Direction::Press)), Step::Button(button, _)) if *prev_button == button =>
+                    *prev_dir = Direction::Click,
+                (_, release) =>
+                    self.script.push(release),
+            }
         }
+        self.script
     }
-    Ok(if script.is_empty() { None } else { Some(script) })
 }
 
 fn warn_led_failure(err: &HidError) {
     warn!("Ignoring failure to blink the MR LED while recording a macro: {err:#?}", );
 }
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::{Axis, Button, Key};
+    use super::*;
+
+    fn selection_in_bank_1(started_at: Instant) -> Selection { Selection::new(1, Duration::from_millis(600), started_at) }
+
+    fn event(action: Action, key: g11_macro_keys::Key) -> Event { Event { action, key } }
+
+    fn record(steps: Vec<Step>) -> Vec<Step> {
+        let mut recording = Recording::default();
+        for step in steps {
+            recording.record(RecordTiming::Drop, step, SystemTime::UNIX_EPOCH);
+        }
+        recording.finish()
+    }
+
+    #[test]
+    fn merges_presses_and_releases_into_clicks() {
+        assert_eq!(
+            record(vec![
+                Step::Key(Key::Tab, Direction::Press), Step::Key(Key::Tab, Direction::Release),
+                Step::Button(Button::Left, Direction::Press), Step::Button(Button::Left, Direction::Release),
+                Step::Button(Button::Right, Direction::Press), Step::Key(Key::Tab, Direction::Click), Step::Button(Button::Right, Direction::Release),
+            ]),
+            vec![
+                Step::Key(Key::Tab, Direction::Click),
+                Step::Button(Button::Left, Direction::Click),
+                Step::Button(Button::Right, Direction::Press), Step::Key(Key::Tab, Direction::Click), Step::Button(Button::Right, Direction::Release),
+            ],
+        );
+    }
+
+    #[test]
+    fn merges_movement_and_scrolling() {
+        assert_eq!(
+            record(vec![
+                Step::MoveMouse(3, 0, Coordinate::Rel), Step::MoveMouse(0, -2, Coordinate::Rel), Step::MoveMouse(1, 1, Coordinate::Rel),
+                Step::Scroll(1, Axis::Vertical), Step::Scroll(1, Axis::Vertical), Step::Scroll(-1, Axis::Horizontal),
+                Step::Button(Button::Left, Direction::Click),
+                Step::MoveMouse(5, 5, Coordinate::Rel),
+            ]),
+            vec![
+                Step::MoveMouse(4, -1, Coordinate::Rel),
+                Step::Scroll(2, Axis::Vertical), Step::Scroll(-1, Axis::Horizontal),
+                Step::Button(Button::Left, Direction::Click),
+                Step::MoveMouse(5, 5, Coordinate::Rel),
+            ],
+        );
+    }
+
+    #[test]
+    fn releases_whatever_is_still_held() {
+        assert_eq!(
+            record(vec![
+                Step::Key(Key::LControl, Direction::Press), Step::Key(Key::LShift, Direction::Press),
+                Step::Key(Key::Tab, Direction::Press), Step::Button(Button::Left, Direction::Press),
+            ]),
+            vec![
+                Step::Key(Key::LControl, Direction::Press), Step::Key(Key::LShift, Direction::Press),
+                Step::Key(Key::Tab, Direction::Press), Step::Button(Button::Left, Direction::Click),
+                Step::Key(Key::Tab, Direction::Release), Step::Key(Key::LShift, Direction::Release), Step::Key(Key::LControl, Direction::Release),
+            ],
+        );
+    }
+
+    #[test]
+    fn drops_releases_of_keys_held_before_recording() {
+        assert_eq!(
+            record(vec![
+                Step::Key(Key::LControl, Direction::Release), Step::Key(Key::Tab, Direction::Press),
+                Step::Key(Key::Unicode('a'), Direction::Release), Step::Key(Key::Tab, Direction::Release),
+            ]),
+            vec![Step::Key(Key::Tab, Direction::Click)],
+        );
+    }
+
+    #[test]
+    fn delays_are_timed_by_when_events_occurred() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut recording = Recording::default();
+        //(as if all four events had been read in a single poll, long after they occurred)
+        for (step, millis) in [
+            (Step::Key(Key::Tab, Direction::Press), 0), (Step::Key(Key::Tab, Direction::Release), 40),
+            (Step::Button(Button::Left, Direction::Press), 250), (Step::Button(Button::Left, Direction::Release), 300),
+        ] {
+            recording.record(RecordTiming::AsRecorded, step, start + Duration::from_millis(millis));
+        }
+        recording.record(RecordTiming::AsRecorded, Step::Key(Key::Tab, Direction::Press), start + Duration::from_millis(290));
+
+        assert_eq!(
+            recording.finish(),
+            vec![
+                Step::Key(Key::Tab, Direction::Click), Step::Sleep(210), Step::Button(Button::Left, Direction::Click),
+                Step::Key(Key::Tab, Direction::Click),
+            ],
+        );
+    }
+
+    #[test]
+    fn chooses_press_or_release_by_how_long_the_g_key_is_held() {
+        let start = Instant::now();
+        let mut selection = selection_in_bank_1(start);
+
+        assert_eq!(selection.consume(event(Action::Pressed, g11_macro_keys::Key::G(4)), start), Choice::Undecided);
+        assert_eq!(
+            selection.consume(event(Action::Released, g11_macro_keys::Key::G(4)), start + Duration::from_millis(100)),
+            Choice::Chosen(Target { m: 1, g: 4, on: Direction::Press }),
+        );
+
+        selection.consume(event(Action::Pressed, g11_macro_keys::Key::G(5)), start);
+        assert!(!selection.is_held_for_release(start + Duration::from_millis(599)));
+        assert!(selection.is_held_for_release(start + Duration::from_millis(600)));
+        assert_eq!(
+            selection.consume(event(Action::Released, g11_macro_keys::Key::G(5)), start + Duration::from_millis(800)),
+            Choice::Chosen(Target { m: 1, g: 5, on: Direction::Release }),
+        );
+    }
+
+    #[test]
+    fn chooses_another_bank_or_cancels() {
+        let now = Instant::now();
+        let mut selection = selection_in_bank_1(now);
+
+        assert_eq!(selection.consume(event(Action::Pressed, g11_macro_keys::Key::M(3)), now), Choice::Bank(3));
+        assert_eq!(selection.consume(event(Action::Released, g11_macro_keys::Key::M(3)), now), Choice::Undecided);
+        selection.consume(event(Action::Pressed, g11_macro_keys::Key::G(18)), now);
+        assert_eq!(
+            selection.consume(event(Action::Released, g11_macro_keys::Key::G(18)), now),
+            Choice::Chosen(Target { m: 3, g: 18, on: Direction::Press }),
+        );
+
+        assert_eq!(selection_in_bank_1(now).consume(event(Action::Released, g11_macro_keys::Key::MR), now), Choice::Cancelled);
+    }
+
+    #[test]
+    fn double_pressing_mr_undoes() {
+        let start = Instant::now();
+
+        let mut selection = selection_in_bank_1(start);
+        selection.consume(event(Action::Pressed, g11_macro_keys::Key::MR), start + Duration::from_millis(250));
+        assert_eq!(selection.consume(event(Action::Released, g11_macro_keys::Key::MR), start + Duration::from_millis(300)), Choice::Undo);
+
+        let mut selection = selection_in_bank_1(start);
+        selection.consume(event(Action::Pressed, g11_macro_keys::Key::MR), start + Duration::from_secs(2));
+        assert_eq!(selection.consume(event(Action::Released, g11_macro_keys::Key::MR), start + Duration::from_secs(2)), Choice::Cancelled);
+    }
+}
@@ -0,0 +1,103 @@
+//! Capture of pointer (mouse) events while recording, read via evdev from `/dev/input/event*`
+
+use std::io;
+use enigo::{Axis, Button, Coordinate, Direction};
+use evdev::{Device, EventSummary, InputEvent, KeyCode, RelativeAxisCode};
+use log::{info, warn};
+use crate::steps::Step;
+use super::TimedStep;
+
+/// Every accessible input device that behaves like a mouse
+pub struct Pointer(Vec<Device>);
+impl Pointer {
+    /// Opens every input device with relative X/Y axes and a left button
+    /// (those that cannot be opened, e.g. due to permissions, are skipped).
+    pub fn open() -> Self {
+        let devices: Vec<Device> = evdev::enumerate()
+            .map(|(_, device)| device)
+            .filter(is_mouse)
+            .filter(|device| device.set_nonblocking(true)
+                .inspect_err(|err| warn!("Not recording from {:?}, which cannot be read without blocking: {err}", device.name()))
+                .is_ok())
+            .collect();
+
+        match devices.as_slice() {
+            [] => warn!("Unable to record pointer events: no mouse could be opened under /dev/input (is this user in the `input` group?)"),
+            devices => info!("Recording pointer events from {:?}", devices.iter().map(Device::name).collect::<Vec<_>>()),
+        }
+        Self(devices)
+    }
+
+    /// Returns the steps for every pointer event since the last poll (without blocking), each stamped with when it occurred
+    pub fn poll(&mut self) -> Vec<TimedStep> {
+        let mut steps = vec![];
+        for device in &mut self.0 {
+            let fetched = device.fetch_events().map(|events| events.filter_map(|event| Some((step(event)?, event.timestamp()))).collect::<Vec<_>>());
+            match fetched {
+                Ok(events) => steps.extend(events),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => warn!("Failed to read pointer events from {:?}: {err}", device.name()),
+            }
+        }
+        steps
+    }
+}
+
+fn is_mouse(device: &Device) -> bool {
+    device.supported_relative_axes().is_some_and(|axes| axes.contains(RelativeAxisCode::REL_X) && axes.contains(RelativeAxisCode::REL_Y))
+        && device.supported_keys().is_some_and(|keys| keys.contains(KeyCode::BTN_LEFT))
+}
+
+/// The step corresponding to a single evdev event (if it is one that gets recorded)
+fn step(event: InputEvent) -> Option<Step> {
+    match event.destructure() {
+        EventSummary::Key(_, code, value) => {
+            let button = match code {
+                KeyCode::BTN_LEFT => Button::Left,
+                KeyCode::BTN_RIGHT => Button::Right,
+                KeyCode::BTN_MIDDLE => Button::Middle,
+                KeyCode::BTN_SIDE => Button::Back,
+                KeyCode::BTN_EXTRA => Button::Forward,
+                _ => return None,
+            };
+            let direction = match value {
+                0 => Direction::Release,
+                1 => Direction::Press,
+                _ => return None, //(auto-repeat)
+            };
+            Some(Step::Button(button, direction))
+        }
+        EventSummary::RelativeAxis(_, RelativeAxisCode::REL_X, dx) => Some(Step::MoveMouse(dx, 0, Coordinate::Rel)),
+        EventSummary::RelativeAxis(_, RelativeAxisCode::REL_Y, dy) => Some(Step::MoveMouse(0, dy, Coordinate::Rel)),
+        //(evdev reports the wheel as positive when scrolling up, whereas enigo takes positive as down)
+        EventSummary::RelativeAxis(_, RelativeAxisCode::REL_WHEEL, notches) => Some(Step::Scroll(-notches, Axis::Vertical)),
+        EventSummary::RelativeAxis(_, RelativeAxisCode::REL_HWHEEL, notches) => Some(Step::Scroll(notches, Axis::Horizontal)),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use evdev::EventType;
+    use super::*;
+
+    fn event(type_: EventType, code: u16, value: i32) -> InputEvent { InputEvent::new(type_.0, code, value) }
+
+    #[test]
+    fn buttons() {
+        assert_eq!(step(event(EventType::KEY, KeyCode::BTN_LEFT.0, 1)), Some(Step::Button(Button::Left, Direction::Press)));
+        assert_eq!(step(event(EventType::KEY, KeyCode::BTN_SIDE.0, 0)), Some(Step::Button(Button::Back, Direction::Release)));
+        assert_eq!(step(event(EventType::KEY, KeyCode::BTN_LEFT.0, 2)), None);
+        assert_eq!(step(event(EventType::KEY, KeyCode::KEY_A.0, 1)), None);
+    }
+
+    #[test]
+    fn movement_and_scrolling() {
+        assert_eq!(step(event(EventType::RELATIVE, RelativeAxisCode::REL_X.0, -3)), Some(Step::MoveMouse(-3, 0, Coordinate::Rel)));
+        assert_eq!(step(event(EventType::RELATIVE, RelativeAxisCode::REL_Y.0, 7)), Some(Step::MoveMouse(0, 7, Coordinate::Rel)));
+        assert_eq!(step(event(EventType::RELATIVE, RelativeAxisCode::REL_WHEEL.0, 1)), Some(Step::Scroll(-1, Axis::Vertical)));
+        assert_eq!(step(event(EventType::RELATIVE, RelativeAxisCode::REL_HWHEEL.0, 1)), Some(Step::Scroll(1, Axis::Horizontal)));
+        assert_eq!(step(event(EventType::SYNCHRONIZATION, 0, 0)), None);
+    }
+}
@@ -1,27 +1,34 @@
 //! Mapping/tracking of regular (104-key) state
 
-use std::collections::HashSet;
 use enigo::Direction;
 use keycode::KeyMap;
+use log::warn;
 use g11_macro_keys::EventError;
 use crate::steps::Step;
-use super::mapping::*;
+use super::{layout::Layout, mapping::*};
 
 /// Keeps track of the known device state,
-/// so that an individual event [`Step`] may be isolated from each set of new bytes received over USB.
+/// so that the event [`Step`]s may be isolated from each set of new bytes received over USB.
 ///
 /// You must keep this object up-to-date by feeding all of the bytes read from the G11's HID interface through [`State::try_consume_event`].
+/// Keys are interpreted according to the given [`Layout`].
 #[derive(Debug, Clone)]
-pub struct State(keycode::KeyModifiers, HashSet<u8>);
+pub struct State(keycode::KeyModifiers, Vec<u8>, Layout);
 impl State {
-    pub fn new() -> Self { Self(keycode::KeyModifiers::empty(), Default::default()) }
+    pub fn new(layout: Layout) -> Self { Self(keycode::KeyModifiers::empty(), Default::default(), layout) }
+
+    pub fn layout(&self) -> &Layout { &self.2 }
 
     /// Updates the [`State`] by inspecting the given bytes (which should have been acquired from the G11's HID interface).
-    /// This, combined with the previously known state, will allow an event [`Step`] to be inferred as the signal's meaning.
+    /// This, combined with the previously known state, will allow the event [`Step`]s to be inferred as the signal's meaning.
+    ///
+    /// A single report may carry several changes at once (e.g. during fast typing or rollover), so every one of them is returned,
+    /// in a stable order: key releases, modifier releases, modifier presses, then key presses.
+    /// (Keys are ordered as they appear in the report, and modifiers by their bit.)
     ///
     /// Note: The G11 keyboard interface emits HID packets of 8 bytes. Anything less will produce an [`EventError`]
     /// The provided buffer may be larger than that, but only the first 8 bytes will be inspected.
-    pub fn try_consume_event(&mut self, usb_bytes: &[u8]) -> Result<Option<Step>, EventError> {
+    pub fn try_consume_event(&mut self, usb_bytes: &[u8]) -> Result<Vec<Step>, EventError> {
         let (modifiers, keypresses) = match usb_bytes {
             [_, _, 1 ..= 3, ..] => Err(EventError::UnreconcilableState), //(KB errors)
             [modifiers, _, keypresses @ ..] => Ok((modifiers, keypresses.iter().take(6))),
@@ -30,31 +37,134 @@ impl State {
 
         let new_modifiers = keycode::KeyModifiers::from_bits(*modifiers).ok_or(EventError::InvalidBytes)?;
         let old_modifiers = std::mem::replace(&mut self.0, new_modifiers);
-        let changed_modifier = new_modifiers.symmetric_difference(old_modifiers);
-        if let Some(key) = key_modifier_to_enigo_key(changed_modifier) {
-            let direction = if old_modifiers.contains(changed_modifier) { Direction::Release } else { Direction::Press };
-            return Ok(Some(Step::Key(key, direction)));
-        }
-
-        let new_keypresses: HashSet<u8> = keypresses.copied()
+        let new_keypresses: Vec<u8> = keypresses.copied()
             .take_while(|keycode| keycode != &0)
             .collect();
-        let old_keypresses = &self.1;
-        if let Some(changed_keypress) = new_keypresses.symmetric_difference(old_keypresses).next().copied() {
-            let result =
-                KeyMap::from_usb_code(0x07, u16::from(changed_keypress)).ok()
-                .map(|keycode| {
-                    let direction = if old_keypresses.contains(&changed_keypress) { Direction::Release } else { Direction::Press };
-                    keycode_to_enigo_key(keycode)
-                        .map(|key| Step::Key(key, direction))
-                        .or(Some(Step::Raw(keycode.xkb, direction)))
-                })
-                .ok_or(EventError::UnreconcilableState);
-
-            self.1 = new_keypresses;
-
-            result
-        }
-        else { Ok(None) }
+        let old_keypresses = std::mem::replace(&mut self.1, new_keypresses);
+        let new_keypresses = &self.1;
+
+        let changed_modifiers = |direction, held: keycode::KeyModifiers| KEY_MODIFIERS.iter()
+            .filter(move |(modifier, _)| held.contains(*modifier) && new_modifiers.symmetric_difference(old_modifiers).contains(*modifier))
+            .map(move |(_, key)| Step::Key(*key, direction));
+        let changed_keypresses = |direction, from: &Vec<u8>, to: &Vec<u8>| from.iter()
+            .filter(|keycode| !to.contains(keycode))
+            .filter_map(|keycode| keypress_step(&self.2, *keycode, direction))
+            .collect::<Vec<_>>();
+
+        let steps = changed_keypresses(Direction::Release, &old_keypresses, new_keypresses).into_iter()
+            .chain(changed_modifiers(Direction::Release, old_modifiers))
+            .chain(changed_modifiers(Direction::Press, new_modifiers))
+            .chain(changed_keypresses(Direction::Press, new_keypresses, &old_keypresses))
+            .collect();
+        Ok(steps)
+    }
+}
+
+/// Keys that cannot be replayed are left out of the recording (with a warning),
+/// rather than aborting the recording or scripting a hardware-specific keycode
+fn keypress_step(layout: &Layout, usb_code: u8, direction: Direction) -> Option<Step> {
+    let Ok(keycode) = KeyMap::from_usb_code(0x07, u16::from(usb_code)) else {
+        warn!("Not recording unknown key (USB usage {usb_code:#04x})");
+        return None;
+    };
+    let key = layout.key(keycode);
+    if key.is_none() {
+        warn!("Not recording {:?} (USB usage {usb_code:#04x}), which cannot be replayed", keycode.id);
+    }
+    key.map(|key| Step::Key(key, direction))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::{Direction::*, Key};
+    use super::*;
+
+    const CONTROL_LEFT: u8 = 0x01;
+    const SHIFT_LEFT: u8 = 0x02;
+    const SHIFT_RIGHT: u8 = 0x20;
+    const ALT_RIGHT: u8 = 0x40;
+    const A: u8 = 0x04;
+    const B: u8 = 0x05;
+    const C: u8 = 0x06;
+
+    fn report(modifiers: u8, keys: &[u8]) -> [u8; 8] {
+        let mut report = [0; 8];
+        report[0] = modifiers;
+        report[2..2 + keys.len()].copy_from_slice(keys);
+        report
+    }
+
+    fn consume(state: &mut State, report: [u8; 8]) -> Vec<Step> {
+        state.try_consume_event(&report).expect("valid report")
+    }
+
+    fn key(key: Key, direction: Direction) -> Step { Step::Key(key, direction) }
+
+    #[test]
+    fn modifier_and_key_pressed_together() {
+        let mut state = State::new(Layout::Us);
+
+        assert_eq!(
+            consume(&mut state, report(SHIFT_LEFT, &[A])),
+            vec![key(Key::LShift, Press), key(Key::Unicode('a'), Press)],
+        );
+        assert_eq!(
+            consume(&mut state, report(0, &[])),
+            vec![key(Key::Unicode('a'), Release), key(Key::LShift, Release)],
+        );
+    }
+
+    #[test]
+    fn rollover() {
+        let mut state = State::new(Layout::Us);
+        consume(&mut state, report(0, &[A, B]));
+
+        assert_eq!(
+            consume(&mut state, report(0, &[B, C])),
+            vec![key(Key::Unicode('a'), Release), key(Key::Unicode('c'), Press)],
+        );
+    }
+
+    #[test]
+    fn several_keys_pressed_together_keep_report_order() {
+        let mut state = State::new(Layout::Us);
+
+        assert_eq!(
+            consume(&mut state, report(0, &[C, A, B])),
+            vec![key(Key::Unicode('c'), Press), key(Key::Unicode('a'), Press), key(Key::Unicode('b'), Press)],
+        );
+    }
+
+    #[test]
+    fn several_modifiers_change_together() {
+        let mut state = State::new(Layout::Us);
+        consume(&mut state, report(SHIFT_LEFT, &[]));
+
+        assert_eq!(
+            consume(&mut state, report(CONTROL_LEFT | SHIFT_RIGHT | ALT_RIGHT, &[])),
+            vec![key(Key::LShift, Release), key(Key::LControl, Press), key(Key::RShift, Press), key(Key::Other(keysym::ISO_LEVEL3_SHIFT), Press)],
+        );
+    }
+
+    #[test]
+    fn unchanged_report_has_no_steps() {
+        let mut state = State::new(Layout::Us);
+        consume(&mut state, report(SHIFT_LEFT, &[A]));
+
+        assert_eq!(consume(&mut state, report(SHIFT_LEFT, &[A])), vec![]);
+    }
+
+    #[test]
+    fn skips_keys_that_cannot_be_replayed() {
+        let mut state = State::new(Layout::Us);
+
+        assert_eq!(consume(&mut state, report(0, &[0xa3, A])), vec![key(Key::Unicode('a'), Press)]);
+        assert_eq!(consume(&mut state, report(0, &[])), vec![key(Key::Unicode('a'), Release)]);
+    }
+
+    #[test]
+    fn rollover_error() {
+        assert!(matches!(State::new(Layout::Us).try_consume_event(&report(0, &[1; 6])), Err(EventError::UnreconcilableState)));
     }
 }
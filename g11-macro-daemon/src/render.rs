@@ -0,0 +1,69 @@
+//! Presentation of bindings to people, as shared by the cheat-sheets, the web UI and the CLI:
+//! brief summaries of scripts, and the escaping of text for HTML (and SVG)
+
+use enigo::{Direction, Key};
+use crate::steps::Step;
+
+/// A brief description of what the script does, e.g. `Control+w`, `"hello"` or `firefox`
+pub fn summarize(script: &[Step]) -> String {
+    let mut held: Vec<String> = vec![];
+    let mut parts: Vec<String> = vec![];
+    for step in script {
+        match step {
+            Step::Text(text) => parts.push(format!("\"{text}\"")),
+            Step::Key(key, Direction::Click) => parts.push(held.iter().cloned().chain([key_name(*key)]).collect::<Vec<_>>().join("+")),
+            Step::Key(key, Direction::Press) => held.push(key_name(*key)),
+            Step::Key(key, Direction::Release) => held.retain(|name| *name != key_name(*key)),
+            Step::Raw(keycode, _) => parts.push(format!("key {keycode}")),
+            Step::Button(button, _) => parts.push(format!("{button:?} click")),
+            Step::MoveMouse(..) => parts.push("move mouse".into()),
+            Step::Scroll(..) => parts.push("scroll".into()),
+            Step::Run(program) => parts.push(program.program().rsplit('/').next().unwrap_or_default().into()),
+            Step::Call(name, _) => parts.push(name.clone()),
+            Step::SetVariable(name, value) => parts.push(format!("{name} = {value}")),
+            Step::IncrementVariable(name, amount) => parts.push(format!("{name} += {amount}")),
+            Step::TypeVariable(name) => parts.push(format!("type {name}")),
+            Step::Sleep(_) => {}
+        }
+    }
+    if !held.is_empty() {
+        parts.push(format!("hold {}", held.join("+")));
+    }
+    parts.dedup();
+    if parts.is_empty() { "(empty)".into() } else { parts.join(" ") }
+}
+
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Unicode(c) => c.to_string(),
+        key => format!("{key:?}"),
+    }
+}
+
+/// Escapes text for inclusion within HTML (whether as content or within a quoted attribute)
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Direction::*;
+    use crate::steps::program::Program;
+    use super::*;
+
+    #[test]
+    fn summarizes_scripts() {
+        assert_eq!(summarize(&[Step::Key(Key::Control, Press), Step::Key(Key::Unicode('w'), Click), Step::Key(Key::Control, Release)]), "Control+w");
+        assert_eq!(summarize(&[Step::Text("hi".into()), Step::Sleep(50), Step::Key(Key::Return, Click)]), "\"hi\" Return");
+        assert_eq!(summarize(&[Step::Run(Program::new("/usr/bin/firefox".into(), vec![]))]), "firefox");
+        assert_eq!(summarize(&[Step::Key(Key::Shift, Press)]), "hold Shift");
+        assert_eq!(summarize(&[Step::Sleep(50)]), "(empty)");
+        assert_eq!(summarize(&[Step::IncrementVariable("invoice".into(), 1), Step::TypeVariable("invoice".into())]), "invoice += 1 type invoice");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape("<a href=\"x\">Tom & Jerry's</a>"), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;");
+    }
+}
@@ -0,0 +1,165 @@
+//! [JSON Schema](https://json-schema.org) describing the key bindings file,
+//! so that editors may offer completion/validation when editing bindings.
+//!
+//! Most of it is derived from the config types themselves, but the [`enigo`] types do not implement [`JsonSchema`],
+//! so they are described by hand here (see the `*Def` marker types, which are referenced via `#[schemars(with = ..)]`).
+
+use std::borrow::Cow;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use crate::config::KeyBinding;
+
+/// Produces the schema for a list of [`KeyBinding`] (the overall format of a key bindings file)
+pub fn key_bindings_schema() -> Schema {
+    schemars::schema_for!(Vec<KeyBinding>)
+}
+
+/// Writes [`key_bindings_schema`] to stdout as pretty-printed JSON
+pub fn print() -> serde_json::Result<()> {
+    serde_json::to_writer_pretty(std::io::stdout().lock(), &key_bindings_schema())
+        .map(|()| println!())
+}
+
+/// Unit variants of [`enigo::Key`] that are available on Linux
+/// (the `Unicode` and `Other` variants carry data, so they are described separately)
+pub const ENIGO_KEY_NAMES: &[&str] = &[
+    "Add", "Alt", "Backspace", "Break", "Begin", "Cancel", "CapsLock", "Clear", "Command", "Control",
+    "Decimal", "Delete", "Divide", "DownArrow", "End", "Escape", "Execute",
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    "F13", "F14", "F15", "F16", "F17", "F18", "F19", "F20", "F21", "F22", "F23", "F24",
+    "F25", "F26", "F27", "F28", "F29", "F30", "F31", "F32", "F33", "F34", "F35",
+    "Find", "Hangul", "Hanja", "Help", "Home", "Insert", "Kanji", "LControl", "LeftArrow", "Linefeed", "LMenu", "LShift",
+    "MediaNextTrack", "MediaPlayPause", "MediaPrevTrack", "MediaStop", "Meta", "ModeChange", "Multiply", "Numlock",
+    "Numpad0", "Numpad1", "Numpad2", "Numpad3", "Numpad4", "Numpad5", "Numpad6", "Numpad7", "Numpad8", "Numpad9",
+    "Option", "PageDown", "PageUp", "Pause", "Print", "PrintScr", "RControl", "Redo", "Return", "RightArrow", "RShift",
+    "ScrollLock", "Select", "ScriptSwitch", "Shift", "ShiftLock", "Space", "Subtract", "Super", "SysReq", "Tab",
+    "Undo", "UpArrow", "VolumeDown", "VolumeMute", "VolumeUp", "MicMute", "Windows",
+];
+
+/// Stand-in for [`enigo::Key`]
+pub struct EnigoKeyDef;
+impl JsonSchema for EnigoKeyDef {
+    fn schema_name() -> Cow<'static, str> { "Key".into() }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A key on the keyboard (see https://docs.rs/enigo/0.5.*/enigo/enum.Key.html)",
+            "oneOf": [
+                { "type": "string", "enum": ENIGO_KEY_NAMES },
+                {
+                    "description": "Any individual character",
+                    "type": "object",
+                    "properties": { "Unicode": { "type": "string", "minLength": 1, "maxLength": 1 } },
+                    "required": ["Unicode"],
+                    "additionalProperties": false,
+                },
+                {
+                    "description": "A raw keysym",
+                    "type": "object",
+                    "properties": { "Other": { "type": "integer", "format": "uint32", "minimum": 0 } },
+                    "required": ["Other"],
+                    "additionalProperties": false,
+                },
+            ],
+        })
+    }
+}
+
+/// Stand-in for [`enigo::Direction`]
+pub struct EnigoDirectionDef;
+impl JsonSchema for EnigoDirectionDef {
+    fn schema_name() -> Cow<'static, str> { "Direction".into() }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        unit_enum("Whether a key/button is pressed, released, or both (clicked)", &["Press", "Release", "Click"])
+    }
+}
+
+/// Stand-in for [`enigo::Button`]
+pub struct EnigoButtonDef;
+impl JsonSchema for EnigoButtonDef {
+    fn schema_name() -> Cow<'static, str> { "Button".into() }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        unit_enum("A mouse button", &[
+            "Left", "Middle", "Right", "Back", "Forward", "ScrollUp", "ScrollDown", "ScrollLeft", "ScrollRight",
+        ])
+    }
+}
+
+/// Stand-in for [`enigo::Axis`]
+pub struct EnigoAxisDef;
+impl JsonSchema for EnigoAxisDef {
+    fn schema_name() -> Cow<'static, str> { "Axis".into() }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        unit_enum("The axis along which to scroll", &["Horizontal", "Vertical"])
+    }
+}
+
+/// Stand-in for [`enigo::Coordinate`]
+pub struct EnigoCoordinateDef;
+impl JsonSchema for EnigoCoordinateDef {
+    fn schema_name() -> Cow<'static, str> { "Coordinate".into() }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        unit_enum("Whether mouse coordinates are absolute or relative to the current position", &["Abs", "Rel"])
+    }
+}
+
+/// Lets the last field of a tuple variant be left out (as serde does, when the field has a default),
+/// which schemars would otherwise require (applied via `#[schemars(transform = ..)]` on the variant)
+pub fn last_field_optional(schema: &mut Schema) {
+    let fields = schema.get_mut("properties").and_then(|properties| properties.as_object_mut()).and_then(|properties| properties.values_mut().next());
+    if let Some(min_items) = fields.and_then(|fields| fields.get_mut("minItems")) {
+        *min_items = (min_items.as_u64().unwrap_or(1).saturating_sub(1)).into();
+    }
+}
+
+fn unit_enum(description: &str, variants: &[&str]) -> Schema {
+    json_schema!({
+        "description": description,
+        "type": "string",
+        "enum": variants,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enigo_key_names_are_deserializable() {
+        for name in ENIGO_KEY_NAMES {
+            ron::from_str::<enigo::Key>(name)
+                .unwrap_or_else(|err| panic!("{name} is not a valid enigo::Key: {err}"));
+        }
+    }
+
+    #[test]
+    fn schema_describes_every_step() {
+        let schema = serde_json::to_string(&key_bindings_schema()).expect("serializes");
+
+        for variant in ["Text", "Key", "Raw", "Button", "MoveMouse", "Scroll", "Run", "Sleep", "Call", "SetVariable", "IncrementVariable", "TypeVariable"] {
+            assert!(schema.contains(&format!("\"{variant}\"")), "schema is missing Step::{variant}");
+        }
+        for definition in ["KeyBinding", "Step", "Program", "Key", "Direction", "Button", "Axis", "Coordinate", "Value"] {
+            assert!(schema.contains(&format!("\"#/$defs/{definition}\"")), "schema is missing a reference to {definition}");
+        }
+    }
+
+    #[test]
+    fn schema_lets_defaulted_fields_be_left_out() {
+        let schema = serde_json::to_value(key_bindings_schema()).expect("serializes");
+        let fields = |variant: &str| schema["$defs"]["Step"]["oneOf"].as_array().expect("variants").iter()
+            .find_map(|step| step["properties"].get(variant).cloned())
+            .unwrap_or_else(|| panic!("schema is missing Step::{variant}"));
+
+        for (variant, min) in [("Call", 1), ("IncrementVariable", 1), ("SetVariable", 2)] {
+            let fields = fields(variant);
+            assert_eq!((fields["minItems"].as_u64(), fields["maxItems"].as_u64()), (Some(min), Some(2)), "Step::{variant} has the wrong number of fields");
+        }
+        assert_eq!(fields("Call")["prefixItems"][1]["additionalProperties"]["type"], "string", "Call's parameters are a map of strings");
+        assert_eq!(fields("TypeVariable")["type"], "string", "TypeVariable takes just a name");
+    }
+}
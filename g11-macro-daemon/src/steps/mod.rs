@@ -1,45 +1,77 @@
-use std::error::Error;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    thread,
+    time::Duration,
+};
 use enigo::{Enigo, Keyboard, Mouse};
 use serde::{Deserialize, Serialize};
-use crate::steps::program::Program;
+use schemars::JsonSchema;
+use crate::{
+    config::{Value, Variables},
+    schema::{self, EnigoAxisDef, EnigoButtonDef, EnigoCoordinateDef, EnigoDirectionDef, EnigoKeyDef},
+    steps::program::Program,
+};
 
 pub mod program;
+pub mod placeholders;
 
 /// Defines the set of actions that may be performed as a step within a macro script
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Step {
     //Start with those supported by Enigo:
 
-    /// See [`enigo::agent::Token::Text`]
+    /// See [`enigo::agent::Token::Text`] (but with its [placeholders](placeholders) expanded)
     Text(String),
     /// See [`enigo::agent::Token::Key`]
-    Key(enigo::Key, enigo::Direction),
+    Key(#[schemars(with = "EnigoKeyDef")] enigo::Key, #[schemars(with = "EnigoDirectionDef")] enigo::Direction),
     /// See [`enigo::agent::Token::Raw`]
-    Raw(u16, enigo::Direction),
+    Raw(u16, #[schemars(with = "EnigoDirectionDef")] enigo::Direction),
     /// See [`enigo::agent::Token::Button`]
-    Button(enigo::Button, enigo::Direction),
+    Button(#[schemars(with = "EnigoButtonDef")] enigo::Button, #[schemars(with = "EnigoDirectionDef")] enigo::Direction),
     /// See [`enigo::agent::Token::MoveMouse`]
-    MoveMouse(i32, i32, enigo::Coordinate),
+    MoveMouse(i32, i32, #[schemars(with = "EnigoCoordinateDef")] enigo::Coordinate),
     /// See [`enigo::agent::Token::Scroll`]
-    Scroll(i32, enigo::Axis),
+    Scroll(i32, #[schemars(with = "EnigoAxisDef")] enigo::Axis),
 
     //Then add our custom steps:
 
-    /// Executes a program (with or without arguments)
+    /// Executes a program (with or without arguments, within which [placeholders](placeholders) are expanded)
     Run(Program),
+    /// Pauses for the given number of milliseconds before continuing with the next step
+    Sleep(u64),
+    /// Runs the steps of the named macro (see [`Macros`](crate::config::Macros)), in place of this one.
+    /// Each `{parameter}` within the macro's `Text` steps and program arguments is replaced with the value given for it (if any).
+    #[schemars(transform = schema::last_field_optional)]
+    Call(String, #[serde(default, skip_serializing_if = "BTreeMap::is_empty")] BTreeMap<String, String>),
+    /// Sets the named variable (see [`Variables`]) to the given number or text
+    SetVariable(String, Value),
+    /// Adds the given amount (1 if left out) to the named numeric variable, which starts from 0
+    #[schemars(transform = schema::last_field_optional)]
+    IncrementVariable(String, #[serde(default = "one")] i64),
+    /// Types the value of the named variable
+    TypeVariable(String),
 }
 
+fn one() -> i64 { 1 }
+
 impl Step {
-    pub fn execute(&self, enigo: &mut Enigo) -> Result<(), Box<dyn Error>> {
+    pub fn execute(&self, enigo: &mut Enigo, variables: &mut Variables) -> Result<(), Box<dyn Error>> {
         match self {
             Step::Key(key, dir) => enigo.key(*key, *dir).map_err(Box::from),
             Step::Raw(key, dir) => enigo.raw(*key, *dir).map_err(Box::from),
-            Step::Text(text) => enigo.text(text).map_err(Box::from),
+            Step::Text(text) => enigo.text(&placeholders::expand(text, variables)).map_err(Box::from),
             Step::Button(button, dir) => enigo.button(*button, *dir).map_err(Box::from),
             Step::MoveMouse(x, y, coordinate) => enigo.move_mouse(*x, *y, *coordinate).map_err(Box::from),
             Step::Scroll(magnitude, axis) => enigo.scroll(*magnitude, *axis).map_err(Box::from),
             
-            Step::Run(program) => program.execute(),
+            Step::Run(program) => program.clone().map_args(|arg| placeholders::expand(&arg, variables)).execute(),
+            Step::Sleep(millis) => { thread::sleep(Duration::from_millis(*millis)); Ok(()) }
+            //(calls are expanded when the binding is loaded; see `config::macros::expand`)
+            Step::Call(name, _) => Err(format!("The call to macro {name:?} was not expanded").into()),
+            Step::SetVariable(name, value) => { variables.set(name, value.clone()); Ok(()) }
+            Step::IncrementVariable(name, amount) => variables.increment(name, *amount).map(|_| ()).map_err(Box::from),
+            Step::TypeVariable(name) => variables.get(name).map_err(Box::from).and_then(|value| enigo.text(&value.to_string()).map_err(Box::from)),
         }
     }
 }
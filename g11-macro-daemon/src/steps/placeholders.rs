@@ -0,0 +1,168 @@
+//! Placeholders within `Text` steps and program arguments, which are replaced with their values at the moment the step is executed:
+//! - `{date:FORMAT}` (or just `{date}`, meaning `{date:%Y-%m-%d}`): the local date/time, in the given
+//!   [`strftime`](https://docs.rs/chrono/latest/chrono/format/strftime/index.html) format
+//! - `{env:NAME}`: the value of the environment variable (or nothing, if it is not set)
+//! - `{clipboard}`: the text on the clipboard (via `wl-paste` on Wayland, or else `xclip`)
+//! - `{hostname}`: the name of this machine
+//! - `{var:NAME}`: the value of the named variable (see [`Variables`])
+//! - `{counter:NAME}`: the value of the named variable, once incremented (so counting from 1, if it was not yet defined)
+//!
+//! Anything else within braces is left as-is, and `{{` and `}}` stand for literal braces (e.g. `{{date}}` types `{date}`).
+
+use std::{
+    env, fs,
+    process::Command,
+};
+use chrono::{format::StrftimeItems, Local};
+use log::warn;
+use crate::config::Variables;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Replaces each placeholder within the text with its current value (and each escaped brace with the brace itself)
+pub fn expand(text: &str, variables: &mut Variables) -> String {
+    scan(text, true, |name| resolve(name, variables))
+}
+
+/// Whether the name (within braces) is that of one of the placeholders, and so cannot be that of a macro's parameter
+pub fn is_builtin(name: &str) -> bool {
+    let (kind, _) = name.split_once(':').unwrap_or((name, ""));
+    matches!(kind, "date" | "env" | "clipboard" | "hostname" | "var" | "counter")
+}
+
+/// Escapes the braces within the text, so that it is typed as-is
+pub fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+/// Replaces each `{name}` within the text with the value given for it by `value_of` (leaving those without a value as-is).
+/// Escaped braces are left escaped, so that they are still literal when the text is [expanded](expand).
+pub fn replace(text: &str, value_of: impl FnMut(&str) -> Option<String>) -> String {
+    scan(text, false, value_of)
+}
+
+/// The name within each `{name}` of the text (not counting escaped braces)
+pub fn names(text: &str) -> Vec<&str> {
+    let mut names = vec![];
+    scan(text, false, |name| {
+        names.push(name);
+        None
+    });
+    names
+}
+
+fn scan<'a>(text: &'a str, unescape: bool, mut value_of: impl FnMut(&'a str) -> Option<String>) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '}']) {
+        replaced.push_str(&rest[..start]);
+        let (brace, after) = (&rest[start..start + 1], &rest[start + 1..]);
+        if let Some(after) = after.strip_prefix(brace) {
+            replaced.push_str(if unescape { brace } else { &rest[start..start + 2] });
+            rest = after;
+            continue;
+        }
+        match after.find('}').filter(|_| brace == "{").and_then(|end| Some((value_of(&after[..end])?, end))) {
+            Some((value, end)) => {
+                replaced.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                replaced.push_str(brace);
+                rest = after;
+            }
+        }
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+fn resolve(name: &str, variables: &mut Variables) -> Option<String> {
+    match name.split_once(':') {
+        Some(("date", format)) => date(format),
+        Some(("env", variable)) => Some(env::var(variable)
+            .inspect_err(|err| warn!("Unable to expand {{{name}}}: {err}"))
+            .unwrap_or_default()),
+        Some(("var", variable)) => Some(variables.get(variable).map(ToString::to_string)
+            .inspect_err(|err| warn!("Unable to expand {{{name}}}: {err}"))
+            .unwrap_or_default()),
+        Some(("counter", variable)) => variables.increment(variable, 1).map(|number| number.to_string())
+            .inspect_err(|err| warn!("Unable to expand {{{name}}}: {err}"))
+            .ok(),
+        None if name == "date" => date(DEFAULT_DATE_FORMAT),
+        None if name == "clipboard" => Some(clipboard()),
+        None if name == "hostname" => Some(hostname()),
+        _ => None,
+    }
+}
+
+fn date(format: &str) -> Option<String> {
+    StrftimeItems::new(format).parse()
+        .map(|items| Local::now().format_with_items(items.into_iter()).to_string())
+        .inspect_err(|_| warn!("Unable to expand {{date:{format}}}, since the format is invalid"))
+        .ok()
+}
+
+fn clipboard() -> String {
+    let (program, args): (&str, &[&str]) = match env::var_os("WAYLAND_DISPLAY") {
+        Some(_) => ("wl-paste", &["--no-newline"]),
+        None => ("xclip", &["-out", "-selection", "clipboard"]),
+    };
+    Command::new(program).args(args).output()
+        .map_err(|err| err.to_string())
+        .and_then(|output| match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            false => Err(format!("{program} exited with {}", output.status)),
+        })
+        .inspect_err(|err| warn!("Unable to read the clipboard: {err}"))
+        .unwrap_or_default()
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname").map(|hostname| hostname.trim().to_owned())
+        .or_else(|_| env::var("HOSTNAME"))
+        .inspect_err(|err| warn!("Unable to determine the hostname: {err}"))
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_placeholders() {
+        let mut variables = Variables::default();
+        let path = env::var("PATH").expect("PATH is set");
+
+        assert_eq!(expand("{env:PATH}", &mut variables), path);
+        assert_eq!(expand("#{counter:invoice}, #{counter:invoice}, #{counter:other}", &mut variables), "#1, #2, #1");
+        assert_eq!(expand("{var:invoice}, {var:nothing}.", &mut variables), "2, .");
+        assert_eq!(expand("{date:%Y}", &mut variables), Local::now().format("%Y").to_string());
+        assert_eq!(expand("{date}", &mut variables).len(), "2026-01-01".len());
+        assert!(!expand("{hostname}", &mut variables).contains('{'));
+    }
+
+    #[test]
+    fn leaves_anything_else_as_is() {
+        let mut variables = Variables::default();
+
+        assert_eq!(expand("fn main() { {} } {ticket} {date:%Q} {", &mut variables), "fn main() { {} } {ticket} {date:%Q} {");
+    }
+
+    #[test]
+    fn types_escaped_braces_as_is() {
+        let mut variables = Variables::default();
+
+        assert_eq!(expand("{{date}} {{env:X}} }}{{ {{{counter:x}}}", &mut variables), "{date} {env:X} }{ {1}");
+        assert_eq!(expand(&escape("fn f() {{}} {hostname}"), &mut variables), "fn f() {{}} {hostname}");
+        assert_eq!(replace("{{a}} {a} }}", |_| Some("b".into())), "{{a}} b }}");
+        assert_eq!(names("{{a}} {b} {{{c}}}"), ["b", "c"]);
+    }
+
+    #[test]
+    fn recognizes_builtins() {
+        assert!(["date", "date:%H", "env:USER", "clipboard", "hostname", "var:x", "counter:x"].into_iter().all(is_builtin));
+        assert!(!["ticket", "dates", "host"].into_iter().any(is_builtin));
+    }
+}
@@ -4,12 +4,24 @@ use std::{
 };
 use std::error::Error;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
 /// The first value is the program, and the second value is the arguments to be passed.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Program(String, #[serde(default)] Vec<String>);
 
 impl Program {
+    pub fn new(program: String, args: Vec<String>) -> Self { Self(program, args) }
+
+    pub fn program(&self) -> &str { &self.0 }
+
+    pub fn args(&self) -> &[String] { &self.1 }
+
+    /// The same program, with each of its arguments transformed by the given function
+    pub fn map_args(self, f: impl FnMut(String) -> String) -> Self {
+        Self(self.0, self.1.into_iter().map(f).collect())
+    }
+
     pub fn execute(&self) -> Result<(), Box<dyn Error>> {
         Command::new(&self.0)
             .args(&self.1)
@@ -0,0 +1,213 @@
+//! State of the configurator, independent of the terminal (so that it may be driven by tests)
+
+use enigo::Direction;
+use g11_macro_keys::{Action, Event, Key};
+use crate::{config::KeyBinding, steps::Step};
+
+/// How many columns of G keys the G11 has
+pub const G_COLUMNS: u8 = 3;
+/// How many rows of G keys the G11 has
+pub const G_ROWS: u8 = 6;
+
+/// Something the user may ask of the configurator
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Moves the cursor by the given number of columns/rows
+    Move(i8, i8),
+    SelectBank(u8),
+    /// Switches between the press and release bindings of the selected key
+    ToggleTrigger,
+    /// Opens the script of the selected binding (creating it, if need be) in the editor
+    Edit,
+    Delete,
+    /// Writes the bindings back to the key bindings file
+    Save,
+    Quit,
+}
+
+pub struct App {
+    /// The bindings of the key bindings file (as edited so far)
+    pub bindings: Vec<KeyBinding>,
+    /// The recorded bindings, which take precedence over the above (shown, but not edited)
+    pub recordings: Vec<KeyBinding>,
+    /// The selected bank (`1 ..= 3`)
+    pub m: u8,
+    /// The selected G key (`1 ..= 18`)
+    pub g: u8,
+    /// The selected trigger
+    pub on: Direction,
+    /// The coordinates of the bindings added, edited or deleted since they were last saved
+    /// (so that only those are written back, lest bindings added to the file by others in the meantime be lost)
+    pub changed: Vec<(u8, u8, Direction)>,
+    /// The keys currently held on the device
+    pub pressed: Vec<Key>,
+    /// Feedback for the user's last command
+    pub status: String,
+}
+impl App {
+    pub fn new(bindings: Vec<KeyBinding>, recordings: Vec<KeyBinding>) -> Self {
+        Self { bindings, recordings, m: 1, g: 1, on: Direction::Press, changed: vec![], pressed: vec![], status: String::new() }
+    }
+
+    /// Applies the commands that concern only the state itself (leaving [`Command::Edit`], [`Command::Save`] and [`Command::Quit`] to the caller)
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::Move(columns, rows) => {
+                let (column, row) = ((self.g - 1) % G_COLUMNS, (self.g - 1) / G_COLUMNS);
+                let column = column.saturating_add_signed(columns).min(G_COLUMNS - 1);
+                let row = row.saturating_add_signed(rows).min(G_ROWS - 1);
+                self.g = row * G_COLUMNS + column + 1;
+            }
+            Command::SelectBank(m @ 1..=3) => self.m = m,
+            Command::SelectBank(_) => {}
+            Command::ToggleTrigger => self.on = match self.on {
+                Direction::Press => Direction::Release,
+                _ => Direction::Press,
+            },
+            Command::Delete => {
+                let (m, g, on) = (self.m, self.g, self.on);
+                let count = self.bindings.len();
+                self.bindings.retain(|binding| !binding.is_at(m, g, on));
+                if self.bindings.len() == count {
+                    self.status = format!("M{m} G{g} has no binding on {on:?} to delete");
+                } else {
+                    self.mark_changed(m, g, on);
+                    self.status = format!("Deleted the binding of M{m} G{g} on {on:?}");
+                }
+            }
+            Command::Edit | Command::Save | Command::Quit => {}
+        }
+    }
+
+    /// Keeps track of the keys held on the device; pressing an M or G key selects it
+    pub fn observe(&mut self, event: Event) {
+        match event {
+            Event { action: Action::Pressed, key } => {
+                self.pressed.push(key);
+                match key {
+                    Key::M(m) => self.m = m,
+                    Key::G(g) => self.g = g,
+                    Key::MR | Key::Backlight => {}
+                }
+            }
+            Event { action: Action::Released, key } => self.pressed.retain(|pressed| *pressed != key),
+        }
+    }
+
+    /// The binding of the key bindings file at the given coordinates (the last one, should there be duplicates)
+    pub fn binding(&self, m: u8, g: u8, on: Direction) -> Option<&KeyBinding> {
+        self.bindings.iter().rfind(|binding| binding.is_at(m, g, on))
+    }
+
+    /// The recorded binding at the given coordinates (which takes precedence over [`Self::binding`])
+    pub fn recording(&self, m: u8, g: u8, on: Direction) -> Option<&KeyBinding> {
+        self.recordings.iter().rfind(|binding| binding.is_at(m, g, on))
+    }
+
+    /// The selected binding, or else a new (empty) one at the selected coordinates
+    pub fn selected(&self) -> KeyBinding {
+        self.binding(self.m, self.g, self.on).cloned()
+            .unwrap_or(KeyBinding::new(self.m, self.g, self.on, vec![]))
+    }
+
+    /// Whether any bindings differ from those last saved
+    pub fn is_dirty(&self) -> bool { !self.changed.is_empty() }
+
+    fn mark_changed(&mut self, m: u8, g: u8, on: Direction) {
+        if !self.changed.contains(&(m, g, on)) {
+            self.changed.push((m, g, on));
+        }
+    }
+
+    /// The bindings that were added or edited, and the coordinates of those that were deleted, since they were last saved
+    pub fn changes(&self) -> (Vec<KeyBinding>, Vec<(u8, u8, Direction)>) {
+        let (mut changed, mut removed) = (vec![], vec![]);
+        for &(m, g, on) in &self.changed {
+            match self.binding(m, g, on) {
+                Some(binding) => changed.push(binding.clone()),
+                None => removed.push((m, g, on)),
+            }
+        }
+        (changed, removed)
+    }
+
+    /// Replaces the script of the selected binding (adding the binding, if need be)
+    pub fn set_script(&mut self, script: Vec<Step>) {
+        let (m, g, on) = (self.m, self.g, self.on);
+        match self.bindings.iter_mut().rfind(|binding| binding.is_at(m, g, on)) {
+            Some(binding) => binding.script = script,
+            None => self.bindings.push(KeyBinding::new(m, g, on, script)),
+        }
+        self.mark_changed(m, g, on);
+        self.status = format!("Updated the binding of M{m} G{g} on {on:?}");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use enigo::Key as EnigoKey;
+    use super::*;
+
+    fn binding(m: u8, g: u8, on: Direction, text: &str) -> KeyBinding {
+        KeyBinding::new(m, g, on, vec![Step::Text(text.into())])
+    }
+
+    #[test]
+    fn moves_within_the_grid() {
+        let mut app = App::new(vec![], vec![]);
+
+        app.apply(Command::Move(1, 1));
+        assert_eq!(app.g, 5);
+        app.apply(Command::Move(5, 0));
+        assert_eq!(app.g, 6);
+        app.apply(Command::Move(0, 9));
+        assert_eq!(app.g, 18);
+        app.apply(Command::Move(-9, -9));
+        assert_eq!(app.g, 1);
+    }
+
+    #[test]
+    fn selects_what_is_pressed_on_the_device() {
+        let mut app = App::new(vec![], vec![]);
+
+        app.observe(Event { key: Key::M(2), action: Action::Pressed });
+        app.observe(Event { key: Key::G(14), action: Action::Pressed });
+        assert_eq!((app.m, app.g), (2, 14));
+        assert_eq!(app.pressed, vec![Key::M(2), Key::G(14)]);
+
+        app.observe(Event { key: Key::M(2), action: Action::Released });
+        assert_eq!(app.pressed, vec![Key::G(14)]);
+    }
+
+    #[test]
+    fn adds_edits_and_deletes_bindings() {
+        let mut app = App::new(vec![binding(1, 1, Direction::Press, "one")], vec![]);
+
+        app.set_script(vec![Step::Key(EnigoKey::Tab, Direction::Click)]);
+        app.apply(Command::ToggleTrigger);
+        app.set_script(vec![Step::Text("released".into())]);
+        assert_eq!(app.bindings, vec![
+            KeyBinding::new(1, 1, Direction::Press, vec![Step::Key(EnigoKey::Tab, Direction::Click)]),
+            binding(1, 1, Direction::Release, "released"),
+        ]);
+
+        app.apply(Command::Delete);
+        assert_eq!(app.bindings.len(), 1);
+        assert!(app.is_dirty());
+        assert_eq!(app.selected(), KeyBinding::new(1, 1, Direction::Release, vec![]));
+    }
+
+    #[test]
+    fn tracks_only_what_was_changed() {
+        let mut app = App::new(vec![binding(1, 1, Direction::Press, "one"), binding(1, 2, Direction::Press, "two")], vec![]);
+
+        app.apply(Command::Move(1, 0));
+        app.apply(Command::Delete);
+        app.apply(Command::SelectBank(2));
+        app.set_script(vec![Step::Text("new".into())]);
+        app.set_script(vec![Step::Text("newer".into())]);
+
+        assert_eq!(app.changes(), (vec![binding(2, 2, Direction::Press, "newer")], vec![(1, 2, Direction::Press)]));
+    }
+}
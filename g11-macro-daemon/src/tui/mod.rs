@@ -0,0 +1,150 @@
+//! Interactive terminal configurator for the key bindings file, showing the G11's macro keys
+//! (with those held on the device highlighted live) and the script of each binding
+
+use std::{io, time::Duration};
+use derive_more::{Display, Error};
+use hidapi::{HidApi, HidDevice};
+use log::warn;
+use ratatui::{
+    crossterm::event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind},
+    DefaultTerminal,
+};
+use g11_macro_keys::usb_id;
+use crate::{config::{self, BindingsFile, LoadError}, control, edit};
+use app::{App, Command};
+
+mod app;
+mod view;
+
+/// How long to wait for terminal input, before checking the device for key presses
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Runs the configurator until the user quits.
+/// Saved changes are applied to the running daemon (if any).
+pub fn run() -> Result<(), TuiError> {
+    let mut app = App::new(
+        config::load_bindings_file(BindingsFile::KeyBindings)?,
+        config::load_bindings_file(BindingsFile::KeyRecordings)?,
+    );
+    //(the daemon need not be stopped: hidraw delivers each report to every reader)
+    let device = HidApi::new()
+        .and_then(|api| api.open(usb_id::VENDOR_LOGITECH, usb_id::PRODUCT_G11_MACRO))
+        .inspect_err(|err| warn!("Unable to open the G11, so pressed keys will not be shown: {err}"))
+        .ok();
+    app.status = match device {
+        Some(_) => "Press a key on the G11 to select it".into(),
+        None => "The G11 could not be opened, so pressed keys are not shown".into(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app, device);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App, mut device: Option<HidDevice>) -> Result<(), TuiError> {
+    let mut device_state = g11_macro_keys::State::default();
+    let mut usb_buf = [0_u8; 9];
+    let mut confirming_quit = false;
+    loop {
+        terminal.draw(|frame| view::draw(frame, app))?;
+
+        //(losing the device, e.g. to it being unplugged, merely stops pressed keys being shown; unsaved changes are kept)
+        while let Some(read) = device.as_ref().map(|device| device.read_timeout(&mut usb_buf, 0)) {
+            match read {
+                Ok(9) => if let Ok(event) = device_state.try_consume_event(&usb_buf) {
+                    app.observe(event);
+                },
+                Ok(_) => break,
+                Err(err) => {
+                    warn!("Unable to read from the G11, so pressed keys are no longer shown: {err}");
+                    app.status = format!("The G11 could no longer be read ({err}), so pressed keys are not shown");
+                    device = None;
+                }
+            }
+        }
+        if !event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+        let TermEvent::Key(key) = event::read()? else { continue };
+        let Some(command) = command_for(key) else { continue };
+
+        match command {
+            Command::Quit if app.is_dirty() && !confirming_quit => {
+                app.status = "There are unsaved changes; press q again to discard them (or s to save)".into();
+                confirming_quit = true;
+                continue;
+            }
+            Command::Quit => return Ok(()),
+            Command::Edit => {
+                ratatui::restore();
+                let edited = edit::edit_script(&app.selected(), |edited| config::validate_saved_calls(std::slice::from_ref(edited)));
+                *terminal = ratatui::init();
+                match edited {
+                    Ok(Some(script)) => app.set_script(script),
+                    Ok(None) => app.status = "Nothing was changed".into(),
+                    Err(err) => app.status = format!("Unable to edit the script: {err}"),
+                }
+            }
+            Command::Save => app.status = save(app),
+            command => app.apply(command),
+        }
+        confirming_quit = false;
+    }
+}
+
+/// Writes the changed bindings (only) back to the key bindings file, and has the daemon apply them, returning the status to show
+fn save(app: &mut App) -> String {
+    let (changed, removed) = app.changes();
+    if changed.is_empty() && removed.is_empty() {
+        return "There are no changes to save".into();
+    }
+    match config::update_key_bindings(&changed, &removed) {
+        Ok(path) => {
+            app.changed.clear();
+            //(so as to show any bindings that others added in the meantime)
+            match config::load_bindings_file(BindingsFile::KeyBindings) {
+                Ok(bindings) => app.bindings = bindings,
+                Err(err) => warn!("Unable to reload the bindings just saved: {err}"),
+            }
+            match control::request_reload() {
+                Ok(()) => format!("Saved {}, and the daemon has reloaded it", path.display()),
+                Err(err) => format!("Saved {} (but it is not yet applied: {err})", path.display()),
+            }
+        }
+        Err(err) => err.to_string(),
+    }
+}
+
+fn command_for(key: KeyEvent) -> Option<Command> {
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+    Some(match key.code {
+        KeyCode::Left | KeyCode::Char('h') => Command::Move(-1, 0),
+        KeyCode::Right | KeyCode::Char('l') => Command::Move(1, 0),
+        KeyCode::Up | KeyCode::Char('k') => Command::Move(0, -1),
+        KeyCode::Down | KeyCode::Char('j') => Command::Move(0, 1),
+        KeyCode::Char(c @ '1'..='3') => Command::SelectBank(c as u8 - b'0'),
+        KeyCode::Tab => Command::ToggleTrigger,
+        KeyCode::Enter | KeyCode::Char('e') => Command::Edit,
+        KeyCode::Delete | KeyCode::Char('d') => Command::Delete,
+        KeyCode::Char('s') => Command::Save,
+        KeyCode::Char('q') | KeyCode::Esc => Command::Quit,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Display, Error)]
+pub enum TuiError {
+    #[display("{_0}")]
+    Loading(LoadError),
+    #[display("Unable to drive the terminal! Cause: {_0}")]
+    Terminal(io::Error),
+}
+impl From<LoadError> for TuiError {
+    fn from(err: LoadError) -> Self { Self::Loading(err) }
+}
+impl From<io::Error> for TuiError {
+    fn from(err: io::Error) -> Self { Self::Terminal(err) }
+}
@@ -0,0 +1,152 @@
+//! Drawing of the configurator: the G11's macro keys (as laid out in the README), alongside the selected key's bindings
+
+use enigo::Direction;
+use g11_macro_keys::Key;
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+    Frame,
+};
+use crate::config::KeyBinding;
+use super::app::{App, G_COLUMNS, G_ROWS};
+
+const HELP: &str = "←↑↓→ select G key · 1-3 bank · Tab press/release · Enter edit · Del delete · s save · q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, status, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(1), Constraint::Length(1)]).areas(frame.area());
+    let [keys, bindings] = Layout::horizontal([Constraint::Length(24), Constraint::Min(0)]).areas(main);
+
+    frame.render_widget(Paragraph::new(key_lines(app)).block(Block::bordered().title(" G11 ")), keys);
+    frame.render_widget(
+        Paragraph::new(binding_lines(app))
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(format!(" M{} G{} ", app.m, app.g))),
+        bindings,
+    );
+    let status_text = if app.is_dirty() { format!("[unsaved] {}", app.status) } else { app.status.clone() };
+    frame.render_widget(Line::from(status_text), status);
+    frame.render_widget(Line::from(HELP).dim(), help);
+}
+
+/// The M keys, then the G keys in rows of three (with a gap after every second row, as on the keyboard)
+fn key_lines(app: &App) -> Vec<Line<'static>> {
+    let m_keys = (1..=3).map(|m| (format!("(M{m})"), Key::M(m)))
+        .chain([(String::from("(MR)"), Key::MR)])
+        .flat_map(|(label, key)| {
+            let style = match key {
+                Key::M(m) if m == app.m => Style::new().fg(Color::Blue).add_modifier(Modifier::BOLD),
+                _ => Style::new(),
+            };
+            [Span::styled(label, pressed(app, key, style)), Span::raw(" ")]
+        });
+    let mut lines = vec![Line::from(m_keys.collect::<Vec<_>>()), Line::default()];
+
+    for row in 0..G_ROWS {
+        let gs: Vec<u8> = (1..=G_COLUMNS).map(|column| row * G_COLUMNS + column).collect();
+        let cells = |text: &dyn Fn(u8) -> String| Line::from(
+            gs.iter().map(|&g| Span::styled(text(g), g_style(app, g))).collect::<Vec<_>>()
+        );
+        lines.push(cells(&|_| "┌───┐".into()));
+        lines.push(cells(&|g| format!("│{:<3}│", format!("G{g}"))));
+        lines.push(cells(&|_| "└───┘".into()));
+        if row % 2 == 1 {
+            lines.push(Line::default());
+        }
+    }
+    lines
+}
+
+fn g_style(app: &App, g: u8) -> Style {
+    let bound = |on| app.recording(app.m, g, on).is_some() || app.binding(app.m, g, on).is_some();
+    let mut style = Style::new();
+    if bound(Direction::Press) || bound(Direction::Release) {
+        style = style.fg(Color::Green);
+    }
+    if g == app.g {
+        style = style.add_modifier(Modifier::REVERSED);
+    }
+    pressed(app, Key::G(g), style)
+}
+
+fn pressed(app: &App, key: Key, style: Style) -> Style {
+    if app.pressed.contains(&key) { style.bg(Color::Yellow).fg(Color::Black) } else { style }
+}
+
+/// The press and release bindings of the selected key (the selected trigger being highlighted)
+fn binding_lines(app: &App) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    for on in [Direction::Press, Direction::Release] {
+        let heading = format!("On {on:?}");
+        lines.push(if on == app.on { Line::from(format!("▶ {heading}")).bold() } else { Line::from(format!("  {heading}")) });
+
+        if let Some(recording) = app.recording(app.m, app.g, on) {
+            lines.push(Line::from("    Overridden by this recorded macro (see `g11-macro-daemon promote`):").italic().cyan());
+            lines.extend(about_line(recording).map(|line| line.cyan()));
+            lines.extend(script_lines(recording).map(|line| line.cyan()));
+        }
+        match app.binding(app.m, app.g, on) {
+            Some(binding) => {
+                lines.extend(about_line(binding));
+                if binding.script.is_empty() {
+                    lines.push(Line::from("    (empty script)").dim());
+                } else {
+                    lines.extend(script_lines(binding));
+                }
+            }
+            None => lines.push(Line::from("    (unbound)").dim()),
+        }
+        lines.push(Line::default());
+    }
+    lines
+}
+
+/// The binding's name and description (if it has either)
+fn about_line(binding: &KeyBinding) -> Option<Line<'static>> {
+    match (&binding.name, &binding.description) {
+        (None, None) => None,
+        (Some(name), None) => Some(Line::from(format!("    {name}")).bold()),
+        (None, Some(description)) => Some(Line::from(format!("    {description}")).italic()),
+        (Some(name), Some(description)) => Some(Line::from(vec![
+            Span::raw(format!("    {name}: ")).bold(),
+            Span::raw(description.clone()).italic(),
+        ])),
+    }
+}
+
+fn script_lines(binding: &KeyBinding) -> impl Iterator<Item = Line<'static>> + '_ {
+    binding.script.iter().map(|step| Line::from(format!("    {}", ron::to_string(step).unwrap_or_else(|_| format!("{step:?}")))))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+    use crate::steps::Step;
+    use super::*;
+
+    fn render(app: &App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 32)).expect("test backend");
+        terminal.draw(|frame| draw(frame, app)).expect("draws");
+        terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn shows_the_layout_and_selected_script() {
+        let mut app = App::new(
+            vec![KeyBinding {
+                name: Some("greet".into()),
+                description: Some("Says hello".into()),
+                ..KeyBinding::new(1, 1, Direction::Press, vec![Step::Text("hello".into())])
+            }],
+            vec![KeyBinding::new(1, 1, Direction::Release, vec![Step::Text("recorded".into())])],
+        );
+        app.changed.push((1, 1, Direction::Press));
+
+        let screen = render(&app);
+        for text in ["(M1)", "(MR)", "│G1 │", "│G18│", "On Press", "greet: Says hello", "Text(\"hello\")", "Text(\"recorded\")", "[unsaved]"] {
+            assert!(screen.contains(text), "{text} is shown");
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! The HTML form controls for editing a script, one row of controls per [`Step`] (suited to its variant),
+//! and the parsing of the submitted fields back into [`Step`]s
+
+use std::{collections::{BTreeMap, HashMap}, str::FromStr};
+use enigo::{Axis, Button, Coordinate, Direction, Key};
+use serde::{de::DeserializeOwned, Serialize};
+use crate::{config::Value, render::escape, schema::ENIGO_KEY_NAMES, steps::{program::Program, Step}};
+
+/// The name of each [`Step`] variant, as offered when adding a step
+pub const STEP_KINDS: [&str; 12] = [
+    "Text", "Key", "Raw", "Button", "MoveMouse", "Scroll", "Run", "Sleep", "Call", "SetVariable", "IncrementVariable", "TypeVariable",
+];
+
+const DIRECTIONS: [&str; 3] = ["Click", "Press", "Release"];
+const BUTTONS: [&str; 9] = ["Left", "Middle", "Right", "Back", "Forward", "ScrollUp", "ScrollDown", "ScrollLeft", "ScrollRight"];
+const AXES: [&str; 2] = ["Vertical", "Horizontal"];
+const COORDINATES: [&str; 2] = ["Rel", "Abs"];
+const VALUE_TYPES: [&str; 2] = ["Number", "Text"];
+
+/// A step of the given kind (see [`STEP_KINDS`]) with default values, for the user to fill in
+pub fn new_step(kind: &str) -> Option<Step> {
+    Some(match kind {
+        "Text" => Step::Text(String::new()),
+        "Key" => Step::Key(Key::Return, Direction::Click),
+        "Raw" => Step::Raw(0, Direction::Click),
+        "Button" => Step::Button(Button::Left, Direction::Click),
+        "MoveMouse" => Step::MoveMouse(0, 0, Coordinate::Rel),
+        "Scroll" => Step::Scroll(1, Axis::Vertical),
+        "Run" => Step::Run(Program::new(String::new(), vec![])),
+        "Sleep" => Step::Sleep(100),
+        "Call" => Step::Call(String::new(), BTreeMap::new()),
+        "SetVariable" => Step::SetVariable(String::new(), Value::Number(0)),
+        "IncrementVariable" => Step::IncrementVariable(String::new(), 1),
+        "TypeVariable" => Step::TypeVariable(String::new()),
+        _ => return None,
+    })
+}
+
+/// Renders the controls for each step (as table rows), with field names prefixed by the step's index
+pub fn render_steps(script: &[Step]) -> String {
+    let mut html = String::new();
+    for (index, step) in script.iter().enumerate() {
+        let field = |name: &str| format!("s{index}.{name}");
+        let (kind, controls) = match step {
+            Step::Text(text) => ("Text", text_input(&field("text"), text, "Text to type")),
+            Step::Key(key, direction) => ("Key", format!(
+                "{}{}", text_input(&field("key"), &key_text(*key), "A character, or a key name"), select(&field("direction"), &DIRECTIONS, &ron_text(direction)),
+            )),
+            Step::Raw(keycode, direction) => ("Raw", format!(
+                "{}{}", number_input(&field("keycode"), &keycode.to_string()), select(&field("direction"), &DIRECTIONS, &ron_text(direction)),
+            )),
+            Step::Button(button, direction) => ("Button", format!(
+                "{}{}", select(&field("button"), &BUTTONS, &ron_text(button)), select(&field("direction"), &DIRECTIONS, &ron_text(direction)),
+            )),
+            Step::MoveMouse(x, y, coordinate) => ("MoveMouse", format!(
+                "x {} y {} {}", number_input(&field("x"), &x.to_string()), number_input(&field("y"), &y.to_string()),
+                select(&field("coordinate"), &COORDINATES, &ron_text(coordinate)),
+            )),
+            Step::Scroll(length, axis) => ("Scroll", format!(
+                "{}{}", number_input(&field("length"), &length.to_string()), select(&field("axis"), &AXES, &ron_text(axis)),
+            )),
+            Step::Run(program) => ("Run", format!(
+                "{}<textarea name=\"{}\" rows=\"2\" placeholder=\"One argument per line\">{}</textarea>",
+                text_input(&field("program"), program.program(), "Program"), field("args"), escape(&program.args().join("\n")),
+            )),
+            Step::Sleep(millis) => ("Sleep", format!("{} ms", number_input(&field("millis"), &millis.to_string()))),
+            Step::Call(name, parameters) => ("Call", format!(
+                "{}<textarea name=\"{}\" rows=\"2\" placeholder=\"One parameter=value per line\">{}</textarea>",
+                text_input(&field("macro"), name, "The name of a macro"), field("parameters"),
+                escape(&parameters.iter().map(|(parameter, value)| format!("{parameter}={value}")).collect::<Vec<_>>().join("\n")),
+            )),
+            Step::SetVariable(name, value) => ("SetVariable", format!(
+                "{} = {}{}", text_input(&field("variable"), name, "Variable"), text_input(&field("value"), &value.to_string(), "Value"),
+                select(&field("type"), &VALUE_TYPES, match value { Value::Number(_) => "Number", Value::Text(_) => "Text" }),
+            )),
+            Step::IncrementVariable(name, amount) => ("IncrementVariable", format!(
+                "{} by {}", text_input(&field("variable"), name, "Variable"), number_input(&field("amount"), &amount.to_string()),
+            )),
+            Step::TypeVariable(name) => ("TypeVariable", text_input(&field("variable"), name, "Variable")),
+        };
+        html.push_str(&format!(
+            "<tr><td>{kind}<input type=\"hidden\" name=\"{}\" value=\"{kind}\"></td><td>{controls}</td>\
+             <td><label><input type=\"checkbox\" name=\"{}\"> remove</label></td></tr>\n",
+            field("kind"), field("remove"),
+        ));
+    }
+    html
+}
+
+/// The key names offered as suggestions for [`Step::Key`] (referenced by each key input)
+pub fn key_names_datalist() -> String {
+    let options: String = ENIGO_KEY_NAMES.iter().map(|name| format!("<option value=\"{name}\">")).collect();
+    format!("<datalist id=\"keys\">{options}</datalist>")
+}
+
+/// Reads back the steps of the submitted form (in order, leaving out those marked for removal).
+/// Returns a message describing the first invalid field, if any.
+pub fn parse_steps(fields: &HashMap<String, String>) -> Result<Vec<Step>, String> {
+    let mut script = vec![];
+    for index in 0.. {
+        let step = StepFields { fields, index };
+        let Some(kind) = step.field("kind") else { break };
+        if step.field("remove").is_some() {
+            continue;
+        }
+
+        script.push(match kind {
+            "Text" => Step::Text(step.field("text").unwrap_or_default().into()),
+            "Key" => Step::Key(parse_key(step.value("key")).ok_or_else(|| step.invalid("key"))?, step.unit("direction")?),
+            "Raw" => Step::Raw(step.number("keycode")?, step.unit("direction")?),
+            "Button" => Step::Button(step.unit("button")?, step.unit("direction")?),
+            "MoveMouse" => Step::MoveMouse(step.number("x")?, step.number("y")?, step.unit("coordinate")?),
+            "Scroll" => Step::Scroll(step.number("length")?, step.unit("axis")?),
+            "Run" if step.value("program").is_empty() => return Err(step.invalid("program")),
+            "Run" => Step::Run(Program::new(
+                step.value("program").into(),
+                step.value("args").lines().map(str::trim).filter(|arg| !arg.is_empty()).map(String::from).collect(),
+            )),
+            "Sleep" => Step::Sleep(step.number("millis")?),
+            "Call" if step.value("macro").is_empty() => return Err(step.invalid("macro")),
+            "Call" => Step::Call(
+                step.value("macro").into(),
+                step.value("parameters").lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| line.split_once('=').map(|(parameter, value)| (parameter.trim().into(), value.trim().into())))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| step.invalid("parameters"))?,
+            ),
+            "SetVariable" | "IncrementVariable" | "TypeVariable" if step.value("variable").is_empty() => return Err(step.invalid("variable")),
+            "SetVariable" => Step::SetVariable(step.value("variable").into(), match step.value("type") {
+                "Number" => Value::Number(step.number("value")?),
+                _ => Value::Text(step.field("value").unwrap_or_default().into()),
+            }),
+            "IncrementVariable" => Step::IncrementVariable(step.value("variable").into(), step.number("amount")?),
+            "TypeVariable" => Step::TypeVariable(step.value("variable").into()),
+            unknown => return Err(format!("Step {} is of an unknown kind: {unknown:?}", index + 1)),
+        });
+    }
+    Ok(script)
+}
+
+/// The submitted fields of the step at the given index
+struct StepFields<'a> {
+    fields: &'a HashMap<String, String>,
+    index: usize,
+}
+impl<'a> StepFields<'a> {
+    fn field(&self, name: &str) -> Option<&'a str> {
+        self.fields.get(&format!("s{}.{name}", self.index)).map(String::as_str)
+    }
+
+    fn value(&self, name: &str) -> &'a str {
+        self.field(name).unwrap_or_default().trim()
+    }
+
+    fn invalid(&self, name: &str) -> String {
+        format!("Step {} ({}) has an invalid {name}: {:?}", self.index + 1, self.value("kind"), self.value(name))
+    }
+
+    fn number<T: FromStr>(&self, name: &str) -> Result<T, String> {
+        self.value(name).parse().map_err(|_| self.invalid(name))
+    }
+
+    fn unit<T: DeserializeOwned>(&self, name: &str) -> Result<T, String> {
+        from_ron(self.value(name)).ok_or_else(|| self.invalid(name))
+    }
+}
+
+/// A single character is typed as-is; anything else is a key name (e.g. `Tab` or `Other(65027)`)
+fn parse_key(text: &str) -> Option<Key> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Key::Unicode(c)),
+        _ => from_ron(text),
+    }
+}
+
+fn key_text(key: Key) -> String {
+    match key {
+        Key::Unicode(c) => c.to_string(),
+        key => ron_text(&key),
+    }
+}
+
+fn from_ron<T: DeserializeOwned>(text: &str) -> Option<T> {
+    ron::from_str(text).ok()
+}
+
+fn ron_text(value: &impl Serialize) -> String {
+    ron::to_string(value).unwrap_or_default()
+}
+
+fn text_input(name: &str, value: &str, placeholder: &str) -> String {
+    let list = if name.ends_with(".key") { " list=\"keys\"" } else { "" };
+    format!("<input type=\"text\" name=\"{name}\" value=\"{}\" placeholder=\"{placeholder}\"{list}>", escape(value))
+}
+
+fn number_input(name: &str, value: &str) -> String {
+    format!("<input type=\"number\" name=\"{name}\" value=\"{value}\">")
+}
+
+fn select(name: &str, options: &[&str], selected: &str) -> String {
+    let options: String = options.iter()
+        .map(|option| format!("<option{}>{option}</option>", if *option == selected { " selected" } else { "" }))
+        .collect();
+    format!("<select name=\"{name}\">{options}</select>")
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Extracts the name/value of each input, selected option and textarea (as a browser would submit them)
+    fn submitted(html: &str) -> HashMap<String, String> {
+        let attribute = |tag: &str, name: &str| tag.split(&format!("{name}=\"")).nth(1).and_then(|rest| rest.split('"').next()).map(String::from);
+        let unescape = |text: &str| text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&#39;", "'").replace("&amp;", "&");
+        let mut fields = HashMap::new();
+        for tag in html.split('<').skip(1) {
+            if tag.starts_with("input") && !tag.contains("checkbox") {
+                fields.insert(attribute(tag, "name").expect("named"), unescape(&attribute(tag, "value").expect("valued")));
+            } else if tag.starts_with("select") {
+                let name = attribute(tag, "name").expect("named");
+                let selected = html.split(&format!("name=\"{name}\">")).nth(1).expect("has options")
+                    .split("</select>").next().expect("closed")
+                    .split("<option selected>").nth(1).expect("has a selection")
+                    .split('<').next().expect("closed");
+                fields.insert(name, selected.into());
+            } else if tag.starts_with("textarea") {
+                let content = tag.split('>').nth(1).unwrap_or_default();
+                fields.insert(attribute(tag, "name").expect("named"), unescape(content));
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn every_kind_of_step_round_trips() {
+        let script = vec![
+            Step::Text("<b>\"hi\" & 'bye'</b>".into()),
+            Step::Key(Key::Unicode('w'), Direction::Click),
+            Step::Key(Key::Other(65027), Direction::Press),
+            Step::Key(Key::Control, Direction::Release),
+            Step::Raw(38, Direction::Click),
+            Step::Button(Button::Back, Direction::Click),
+            Step::MoveMouse(-5, 10, Coordinate::Abs),
+            Step::Scroll(-2, Axis::Horizontal),
+            Step::Run(Program::new("notify-send".into(), vec!["Hello there".into(), "--urgency=low".into()])),
+            Step::Sleep(250),
+            Step::Call("close-tab".into(), BTreeMap::new()),
+            Step::Call("jira-comment".into(), [("ticket".into(), "OPS-12".into()), ("body".into(), "a=b".into())].into()),
+            Step::SetVariable("invoice".into(), Value::Number(1000)),
+            Step::SetVariable("status".into(), Value::Text("42 ".into())),
+            Step::IncrementVariable("invoice".into(), -2),
+            Step::TypeVariable("invoice".into()),
+        ];
+
+        assert_eq!(parse_steps(&submitted(&render_steps(&script))), Ok(script));
+    }
+
+    #[test]
+    fn new_steps_of_every_kind_are_renderable() {
+        for kind in STEP_KINDS {
+            let step = new_step(kind).expect("known kind");
+            assert!(render_steps(&[step]).contains(&format!("value=\"{kind}\"")));
+        }
+        assert_eq!(new_step("Teleport"), None);
+    }
+
+    #[test]
+    fn removes_marked_steps_and_reports_invalid_ones() {
+        let fields = |pairs: &[(&str, &str)]| pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+
+        assert_eq!(
+            parse_steps(&fields(&[("s0.kind", "Sleep"), ("s0.millis", "5"), ("s0.remove", "on"), ("s1.kind", "Text"), ("s1.text", "kept")])),
+            Ok(vec![Step::Text("kept".into())]),
+        );
+        assert!(parse_steps(&fields(&[("s0.kind", "Sleep"), ("s0.millis", "soon")])).is_err_and(|err| err.contains("millis")));
+        assert!(parse_steps(&fields(&[("s0.kind", "Key"), ("s0.key", "NoSuchKey"), ("s0.direction", "Click")])).is_err());
+        assert!(parse_steps(&fields(&[("s0.kind", "Run"), ("s0.program", " ")])).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! Opt-in web UI for editing bindings from a browser, served by the daemon on localhost only
+//! (see [`Settings::web_ui_port`](crate::config::Settings::web_ui_port)).
+//!
+//! Edited scripts are saved in the same way as recorded macros (see [`config::save_recorded_macro`]),
+//! and passed on to the daemon to be applied straight away (see [`WebUi::try_saved`]).
+
+use std::{
+    collections::HashMap,
+    io::{self, Cursor},
+    net::{Ipv4Addr, SocketAddr},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+use enigo::Direction;
+use log::{info, warn};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::{
+    config::{self, BindingsFile, KeyBinding, LoadError, SaveRecordedMacroError},
+    render::{escape, summarize},
+};
+
+mod form;
+
+/// The bindings of both files, as currently saved
+pub struct Bindings {
+    pub key_bindings: Vec<KeyBinding>,
+    pub recordings: Vec<KeyBinding>,
+}
+impl Bindings {
+    fn load() -> Result<Self, LoadError> {
+        Ok(Self {
+            key_bindings: config::load_bindings_file(BindingsFile::KeyBindings)?,
+            recordings: config::load_bindings_file(BindingsFile::KeyRecordings)?,
+        })
+    }
+
+    /// The binding in effect at the given coordinates (a recording taking precedence over a static binding)
+    fn effective(&self, m: u8, g: u8, on: Direction) -> Option<(&KeyBinding, BindingsFile)> {
+        self.recordings.iter().rfind(|binding| binding.is_at(m, g, on)).map(|binding| (binding, BindingsFile::KeyRecordings))
+            .or_else(|| self.key_bindings.iter().rfind(|binding| binding.is_at(m, g, on)).map(|binding| (binding, BindingsFile::KeyBindings)))
+    }
+}
+
+/// Serves the web UI on a dedicated thread, passing on each saved binding to be collected via [`WebUi::try_saved`]
+pub struct WebUi(Receiver<KeyBinding>);
+impl WebUi {
+    pub fn spawn(port: u16) -> io::Result<Self> {
+        Self::spawn_with(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), Bindings::load, config::save_recorded_macro)
+            .map(|(web_ui, address)| {
+                info!("Serving the web UI at http://{address}");
+                web_ui
+            })
+    }
+
+    fn spawn_with(
+        address: SocketAddr,
+        load: impl Fn() -> Result<Bindings, LoadError> + Send + 'static,
+        save: impl Fn(KeyBinding) -> Result<(), SaveRecordedMacroError> + Send + 'static,
+    ) -> io::Result<(Self, SocketAddr)> {
+        let server = Server::http(address).map_err(io::Error::other)?;
+        let address = server.server_addr().to_ip().ok_or_else(|| io::Error::other("not listening on an IP address"))?;
+        let (saved_sender, saved) = mpsc::channel();
+        let site = Site { port: address.port(), load: Box::new(load), save: Box::new(save), saved: saved_sender };
+
+        thread::Builder::new()
+            .name("web-ui".into())
+            .spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let response = site.respond(&mut request);
+                    let _ = request.respond(response)
+                        .inspect_err(|err| warn!("Failed to respond to a web UI request: {err}"));
+                }
+            })?;
+        Ok((Self(saved), address))
+    }
+
+    /// Returns the next binding saved via the web UI, if any (without blocking)
+    pub fn try_saved(&self) -> Option<KeyBinding> {
+        self.0.try_recv().ok()
+    }
+}
+
+type Page = Response<Cursor<Vec<u8>>>;
+
+struct Site {
+    port: u16,
+    load: Box<dyn Fn() -> Result<Bindings, LoadError> + Send>,
+    save: Box<dyn Fn(KeyBinding) -> Result<(), SaveRecordedMacroError> + Send>,
+    saved: Sender<KeyBinding>,
+}
+impl Site {
+    fn respond(&self, request: &mut Request) -> Page {
+        //Any website may have the browser send requests here, so only those addressed to (and coming from) this UI are honoured:
+        //checking the Host guards against DNS rebinding, and checking the Origin (or else the Referer) guards against cross-site form submission.
+        //A submission with neither is refused, since browsers send at least one of them with forms of their own.
+        let local = [format!("127.0.0.1:{}", self.port), format!("localhost:{}", self.port)];
+        let is_local_url = |url: &str| local.iter().map(|host| format!("http://{host}"))
+            .any(|origin| url.strip_prefix(&origin).is_some_and(|path| path.is_empty() || path.starts_with('/')));
+        let header = |name: &'static str| request.headers().iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().to_owned());
+        if !header("Host").is_some_and(|host| local.contains(&host)) {
+            return error_page(403, "Only requests to this computer's own address are accepted");
+        }
+        if *request.method() != Method::Get && !header("Origin").or_else(|| header("Referer")).is_some_and(|source| is_local_url(&source)) {
+            return error_page(403, "Only forms submitted from this UI are accepted");
+        }
+
+        let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+        let query = parse_form(query);
+        let coordinates = || Some((
+            query.get("m")?.parse().ok().filter(|m| (1..=3).contains(m))?,
+            query.get("g")?.parse().ok().filter(|g| (1..=18).contains(g))?,
+            match query.get("on").map(String::as_str) { Some("Release") => Direction::Release, _ => Direction::Press },
+        ));
+
+        let bindings = match (self.load)() {
+            Ok(bindings) => bindings,
+            Err(err) => return error_page(500, &err.to_string()),
+        };
+        match (request.method(), path) {
+            (Method::Get, "/") => html_page(200, &index(&bindings, query.get("m").and_then(|m| m.parse().ok()).unwrap_or(1))),
+            (Method::Get, "/edit") => match coordinates() {
+                Some((m, g, on)) => {
+                    let binding = bindings.effective(m, g, on).map(|(binding, _)| binding.clone())
+                        .unwrap_or_else(|| KeyBinding::new(m, g, on, vec![]));
+                    html_page(200, &edit(&bindings, &binding, None))
+                }
+                None => error_page(400, "Expected m=1..3, g=1..18 and on=Press|Release"),
+            },
+            (Method::Post, "/edit") => match coordinates() {
+                Some((m, g, on)) => {
+                    let mut body = String::new();
+                    if let Err(err) = request.as_reader().read_to_string(&mut body) {
+                        return error_page(400, &err.to_string());
+                    }
+                    self.submit(&bindings, m, g, on, &parse_form(&body))
+                }
+                None => error_page(400, "Expected m=1..3, g=1..18 and on=Press|Release"),
+            },
+            _ => error_page(404, "There is no such page"),
+        }
+    }
+
+    fn submit(&self, bindings: &Bindings, m: u8, g: u8, on: Direction, fields: &HashMap<String, String>) -> Page {
+        let text = |name: &str| fields.get(name).map(|text| text.trim().to_owned()).filter(|text| !text.is_empty());
+        let mut binding = KeyBinding { name: text("name"), description: text("description"), label: text("label"), ..KeyBinding::new(m, g, on, vec![]) };
+        match form::parse_steps(fields) {
+            Ok(script) => binding.script = script,
+            Err(err) => return html_page(400, &edit(bindings, &binding, Some(&err))),
+        };
+        if fields.get("action").map(String::as_str) == Some("add") {
+            binding.script.extend(fields.get("add_kind").and_then(|kind| form::new_step(kind)));
+            return html_page(200, &edit(bindings, &binding, None));
+        }
+
+        match (self.save)(binding.clone()) {
+            Ok(()) => {
+                info!("Saved {} via the web UI", binding.describe());
+                let _ = self.saved.send(binding);
+                Response::from_data(vec![]).with_status_code(303).with_header(header("Location", &format!("/?m={m}")))
+            }
+            Err(err @ SaveRecordedMacroError::InvalidMacros(_)) => html_page(400, &edit(bindings, &binding, Some(&err.to_string()))),
+            Err(err) => html_page(500, &edit(bindings, &binding, Some(&err.to_string()))),
+        }
+    }
+}
+
+/// The G keys of the given bank, in rows of three (with a gap after every second row, as on the keyboard)
+fn index(bindings: &Bindings, m: u8) -> String {
+    let tabs: String = (1..=3)
+        .map(|bank| format!("<a href=\"/?m={bank}\"{}>M{bank}</a> ", if bank == m { " class=\"active\"" } else { "" }))
+        .collect();
+    let mut rows = String::new();
+    for row in 0..6 {
+        rows.push_str(if row % 2 == 0 && row > 0 { "<tr class=\"gap\">" } else { "<tr>" });
+        for g in (1..=3).map(|column| row * 3 + column) {
+            let trigger = |on: Direction| {
+                let summary = match bindings.effective(m, g, on) {
+                    Some((binding, file)) => format!(
+                        "{}{}", escape(&binding.name.clone().unwrap_or_else(|| summarize(&binding.script))),
+                        if file == BindingsFile::KeyRecordings { " <small>(recorded)</small>" } else { "" },
+                    ),
+                    None => "<small>unbound</small>".into(),
+                };
+                format!("<a href=\"/edit?m={m}&amp;g={g}&amp;on={on:?}\">{on:?}</a>: {summary}")
+            };
+            rows.push_str(&format!(
+                "<td><b>G{g}</b><br>{}<br>{}</td>",
+                trigger(Direction::Press), trigger(Direction::Release),
+            ));
+        }
+        rows.push_str("</tr>\n");
+    }
+    format!("<h1>G11 macros</h1><nav>{tabs}</nav><table class=\"keys\">{rows}</table>")
+}
+
+fn edit(bindings: &Bindings, binding: &KeyBinding, error: Option<&str>) -> String {
+    let KeyBinding { m, g, on, .. } = *binding;
+    let source = match bindings.effective(m, g, on) {
+        Some((_, BindingsFile::KeyRecordings)) => "This key currently has a recorded macro.",
+        Some((_, BindingsFile::KeyBindings)) => "This key is currently bound in key_bindings.ron; saving overrides that binding with a recorded one.",
+        None => "This key is currently unbound.",
+    };
+    let error = error.map_or(String::new(), |error| format!("<p class=\"error\">{}</p>", escape(error)));
+    let kinds: String = form::STEP_KINDS.iter().map(|kind| format!("<option>{kind}</option>")).collect();
+    format!(
+        "<h1>M{m} G{g}, on {on:?}</h1><p>{source} Saved scripts go to {}, like recorded macros.</p>{error}\
+         <form method=\"post\" action=\"/edit?m={m}&amp;g={g}&amp;on={on:?}\">\
+         <p><label>Name <input type=\"text\" name=\"name\" value=\"{}\" placeholder=\"e.g. close-tab\"></label> \
+         <label>Label <input type=\"text\" name=\"label\" value=\"{}\" placeholder=\"For cheat-sheets\"></label></p>\
+         <p><label>Description <input type=\"text\" name=\"description\" value=\"{}\" size=\"60\"></label></p>\
+         <table class=\"steps\">{}</table>\
+         <p><select name=\"add_kind\">{kinds}</select> <button name=\"action\" value=\"add\">Add step</button></p>\
+         <p><button name=\"action\" value=\"save\">Save</button> <a href=\"/?m={m}\">Cancel</a></p>\
+         {}</form>",
+        config::XDG_CONFIG_KEY_RECORDINGS,
+        escape(binding.name.as_deref().unwrap_or_default()),
+        escape(binding.label.as_deref().unwrap_or_default()),
+        escape(binding.description.as_deref().unwrap_or_default()),
+        form::render_steps(&binding.script), form::key_names_datalist(),
+    )
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+    nav a{padding:.3em .8em;border:1px solid #888;border-radius:4px;text-decoration:none}nav a.active{background:#36c;color:#fff}\
+    table.keys td{border:1px solid #888;border-radius:6px;padding:.5em;width:14em;vertical-align:top}tr.gap td{border-top:1.5em solid transparent}\
+    table.steps td{padding:.2em .5em}.error{color:#c00}";
+
+fn html_page(status: u16, body: &str) -> Page {
+    let html = format!("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>G11 macros</title><style>{STYLE}</style></head><body>{body}</body></html>");
+    Response::from_string(html).with_status_code(status).with_header(header("Content-Type", "text/html; charset=utf-8"))
+}
+
+fn error_page(status: u16, message: &str) -> Page {
+    html_page(status, &format!("<h1>Error {status}</h1><p>{}</p><p><a href=\"/\">Back</a></p>", escape(message)))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header names/values are valid")
+}
+
+/// Decodes `application/x-www-form-urlencoded` text (as used by both query strings and form submissions)
+fn parse_form(text: &str) -> HashMap<String, String> {
+    fn decode(text: &str) -> String {
+        let bytes = text.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut index = 0;
+        while index < bytes.len() {
+            match bytes[index] {
+                b'+' => decoded.push(b' '),
+                b'%' => match text.get(index + 1..index + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        index += 2;
+                    }
+                    None => decoded.push(b'%'),
+                },
+                byte => decoded.push(byte),
+            }
+            index += 1;
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+
+    text.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .map(|(name, value)| (decode(name), decode(value)))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::{Arc, Mutex},
+    };
+    use crate::steps::Step;
+    use super::*;
+
+    /// Serves the web UI on an ephemeral port, with the bindings kept in memory
+    fn serve(key_bindings: Vec<KeyBinding>) -> (WebUi, SocketAddr, Arc<Mutex<Vec<KeyBinding>>>) {
+        let recordings = Arc::new(Mutex::new(vec![]));
+        let (loaded, saved) = (recordings.clone(), recordings.clone());
+        let (web_ui, address) = WebUi::spawn_with(
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            move || Ok(Bindings { key_bindings: key_bindings.clone(), recordings: loaded.lock().unwrap().clone() }),
+            move |binding| {
+                saved.lock().unwrap().push(binding);
+                Ok(())
+            },
+        ).expect("serves");
+        (web_ui, address, recordings)
+    }
+
+    /// Sends a request (from this UI, unless other headers are given), returning the status and the response
+    fn request(address: SocketAddr, method: &str, path: &str, headers: &str, body: &str) -> (u16, String) {
+        let headers = if headers.is_empty() { format!("Host: {address}\r\nOrigin: http://{address}\r\n") } else { headers.into() };
+        let mut stream = TcpStream::connect(address).expect("connects");
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\n{headers}Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len(),
+        ).expect("sends");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("receives");
+        let status = response.split(' ').nth(1).and_then(|status| status.parse().ok()).expect("has a status");
+        (status, response)
+    }
+
+    #[test]
+    fn shows_the_keys_of_the_selected_bank() {
+        let (_web_ui, address, _) = serve(vec![
+            KeyBinding::new(2, 7, Direction::Press, vec![Step::Text("<hi>".into())]),
+        ]);
+
+        let (status, page) = request(address, "GET", "/?m=2", "", "");
+        assert_eq!(status, 200);
+        for text in ["G1</b>", "G18</b>", "&quot;&lt;hi&gt;&quot;", "/edit?m=2&amp;g=7&amp;on=Release"] {
+            assert!(page.contains(text), "{text} is shown");
+        }
+
+        let (status, page) = request(address, "GET", "/edit?m=2&g=7&on=Press", "", "");
+        assert_eq!(status, 200);
+        assert!(page.contains("value=\"&lt;hi&gt;\""));
+    }
+
+    #[test]
+    fn saves_edited_scripts() {
+        let (web_ui, address, recordings) = serve(vec![]);
+
+        let (status, page) = request(address, "POST", "/edit?m=1&g=3&on=Release", "", "s0.kind=Text&s0.text=a+b%26c&action=add&add_kind=Sleep");
+        assert_eq!(status, 200);
+        assert!(page.contains("name=\"s1.millis\""), "a step has been added");
+        assert!(recordings.lock().unwrap().is_empty(), "nothing has been saved yet");
+
+        let (status, _) = request(address, "POST", "/edit?m=1&g=3&on=Release", "", "s0.kind=Text&s0.text=a+b%26c&s1.kind=Sleep&s1.millis=50&label=+Greet+&name=greet&description=&action=save");
+        assert_eq!(status, 303);
+        let expected = KeyBinding {
+            name: Some("greet".into()),
+            label: Some("Greet".into()),
+            ..KeyBinding::new(1, 3, Direction::Release, vec![Step::Text("a b&c".into()), Step::Sleep(50)])
+        };
+        assert_eq!(*recordings.lock().unwrap(), vec![expected.clone()]);
+        assert_eq!(web_ui.try_saved(), Some(expected));
+        assert_eq!(web_ui.try_saved(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_steps() {
+        let (web_ui, address, recordings) = serve(vec![]);
+
+        let (status, page) = request(address, "POST", "/edit?m=1&g=1", "", "s0.kind=Sleep&s0.millis=soon&action=save");
+        assert_eq!(status, 400);
+        assert!(page.contains("invalid millis"));
+        assert!(recordings.lock().unwrap().is_empty());
+        assert_eq!(web_ui.try_saved(), None);
+    }
+
+    #[test]
+    fn rejects_requests_from_elsewhere() {
+        let (_web_ui, address, recordings) = serve(vec![]);
+        let port = address.port();
+
+        assert_eq!(request(address, "GET", "/", &format!("Host: evil.example:{port}\r\n"), "").0, 403);
+        assert_eq!(request(address, "GET", "/", &format!("Host: localhost:{port}\r\n"), "").0, 200);
+        assert_eq!(
+            request(address, "POST", "/edit?m=1&g=1", &format!("Host: {address}\r\nOrigin: http://evil.example\r\n"), "s0.kind=Sleep&s0.millis=1&action=save").0,
+            403,
+        );
+        assert_eq!(request(address, "POST", "/edit?m=1&g=1", &format!("Host: {address}\r\n"), "s0.kind=Sleep&s0.millis=1&action=save").0, 403);
+        assert_eq!(
+            request(address, "POST", "/edit?m=1&g=1", &format!("Host: {address}\r\nReferer: http://{address}.evil.example/\r\n"), "s0.kind=Sleep&s0.millis=1&action=save").0,
+            403,
+        );
+        assert!(recordings.lock().unwrap().is_empty());
+        assert_eq!(
+            request(address, "POST", "/edit?m=1&g=1", &format!("Host: {address}\r\nReferer: http://{address}/edit?m=1&g=1\r\n"), "s0.kind=Sleep&s0.millis=1&action=save").0,
+            303,
+        );
+        assert_eq!(recordings.lock().unwrap().len(), 1);
+        assert_eq!(request(address, "GET", "/nowhere", "", "").0, 404);
+    }
+}